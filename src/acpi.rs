@@ -0,0 +1,166 @@
+//! Just enough ACPI to find the processors.
+//!
+//! The RSDP is searched in the BIOS memory areas, and the MADT is looked up
+//! through the RSDT (or the XSDT on ACPI 2.0 and later). All the tables are
+//! read through the bootloader's mapping of the physical memory.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+
+use x86_64::PhysAddr;
+
+use crate::memory::phys_to_virt;
+
+/// Header shared by all the system description tables
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+/// Root System Description Pointer, the `xsdt_address` is only valid from
+/// revision 2
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Size of the ACPI 1.0 part of the RSDP, covered by `checksum`
+const RSDP_V1_SIZE: usize = 20;
+
+/// MADT entry types
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+/// Set in the flags of a local APIC entry if the processor can be used
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+
+/// What the MADT (Multiple APIC Description Table) tells about the CPUs
+#[derive(Debug, Clone)]
+pub struct Madt {
+    /// Physical address of the local APIC registers of each CPU
+    pub local_apic_address: PhysAddr,
+    /// The usable processors, the bootstrap processor included
+    pub processors: Vec<Processor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Processor {
+    pub acpi_id: u8,
+    pub apic_id: u8,
+}
+
+/// Reads the MADT, returns `None` if there are no ACPI tables or no MADT.
+pub fn madt() -> Option<Madt> {
+    let madt = find_table(b"APIC")?;
+    let header: SdtHeader = unsafe { read_phys(madt) };
+    let mut local_apic_address = PhysAddr::new(unsafe {
+        read_phys::<u32>(madt + size_of::<SdtHeader>())
+    } as u64);
+    let mut processors = Vec::new();
+
+    // the local APIC address and the flags come before the entries
+    let mut entry = madt + size_of::<SdtHeader>() + 8u64;
+    let end = madt + header.length as u64;
+    while entry < end {
+        let (kind, length): (u8, u8) = unsafe { (read_phys(entry), read_phys(entry + 1u64)) };
+        match kind {
+            MADT_LOCAL_APIC => {
+                let acpi_id: u8 = unsafe { read_phys(entry + 2u64) };
+                let apic_id: u8 = unsafe { read_phys(entry + 3u64) };
+                let flags: u32 = unsafe { read_phys(entry + 4u64) };
+                if flags & LOCAL_APIC_ENABLED != 0 {
+                    processors.push(Processor { acpi_id, apic_id });
+                }
+            }
+            MADT_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                local_apic_address = PhysAddr::new(unsafe { read_phys(entry + 4u64) });
+            }
+            _ => {}
+        }
+        if length == 0 {
+            // a broken table would loop forever
+            break;
+        }
+        entry += length as u64;
+    }
+
+    Some(Madt { local_apic_address, processors })
+}
+
+/// Returns the physical address of the table with `signature`.
+fn find_table(signature: &[u8; 4]) -> Option<PhysAddr> {
+    let rsdp_address = find_rsdp()?;
+    let rsdp: Rsdp = unsafe { read_phys(rsdp_address) };
+
+    // pointers are 64 bits wide in the XSDT, 32 bits in the RSDT
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (PhysAddr::new(rsdp.xsdt_address), 8)
+    } else {
+        (PhysAddr::new(rsdp.rsdt_address as u64), 4)
+    };
+    let header: SdtHeader = unsafe { read_phys(root) };
+    let entries = (header.length as usize - size_of::<SdtHeader>()) / entry_size;
+
+    (0..entries)
+        .map(|i| {
+            let entry = root + size_of::<SdtHeader>() + i * entry_size;
+            PhysAddr::new(match entry_size {
+                8 => unsafe { read_phys::<u64>(entry) },
+                _ => (unsafe { read_phys::<u32>(entry) }) as u64,
+            })
+        })
+        .find(|&table| {
+            let header: SdtHeader = unsafe { read_phys(table) };
+            &header.signature == signature && checksum_ok(table, header.length as usize)
+        })
+}
+
+/// Searches the RSDP in the first KiB of the EBDA, then in the BIOS ROM.
+fn find_rsdp() -> Option<PhysAddr> {
+    // the real mode segment of the EBDA is stored at 0x40e
+    let ebda = (unsafe { read_phys::<u16>(PhysAddr::new(0x40e)) } as u64) << 4;
+    let areas = [(ebda, ebda + 1024), (0xe0000, 0x100000)];
+
+    areas.into_iter()
+        .filter(|&(start, _)| start != 0)
+        .flat_map(|(start, end)| (start..end).step_by(16))
+        .map(PhysAddr::new)
+        .find(|&address| {
+            let signature: [u8; 8] = unsafe { read_phys(address) };
+            &signature == b"RSD PTR " && checksum_ok(address, RSDP_V1_SIZE)
+        })
+}
+
+/// ACPI structures are valid when all their bytes sum to 0.
+fn checksum_ok(address: PhysAddr, length: usize) -> bool {
+    (0..length)
+        .map(|i| unsafe { read_phys::<u8>(address + i) })
+        .fold(0u8, |sum, byte| sum.wrapping_add(byte))
+        == 0
+}
+
+/// Reads a `T` at the physical `address`.
+///
+/// Safety: `address` must be in memory that can be read as a `T`.
+unsafe fn read_phys<T: Copy>(address: PhysAddr) -> T {
+    read_unaligned(phys_to_virt(address).as_ptr())
+}
@@ -2,6 +2,8 @@ use core::alloc::{Layout, GlobalAlloc};
 use core::ptr::NonNull;
 use core::{ptr, mem};
 
+use x86_64::instructions::interrupts;
+
 use super::Locked;
 
 // use
@@ -57,51 +59,58 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
 }
 
+/// The lock is held with interrupts disabled, so that a thread can not be
+/// preempted while holding it, and allocating from interrupt handlers can
+/// not deadlock.
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock();
-        match list_index(&layout) {
-            Some(index) => {
-                match allocator.list_heads[index].take() {
-                    Some(node) => {
-                        allocator.list_heads[index] = node.next.take();
-                        node as *mut ListNode as *mut u8
-                    }
-                    None => {
-                        // no block exists in list => allocate new block
-                        let block_size = BLOCK_SIZES[index];
-                        // only works if all block sizes are a power of 2
-                        let block_align = block_size;
-                        let layout = Layout::from_size_align(block_size, block_align).unwrap();
-                        allocator.fallback_alloc(layout)
+        interrupts::without_interrupts(|| {
+            let mut allocator = self.lock();
+            match list_index(&layout) {
+                Some(index) => {
+                    match allocator.list_heads[index].take() {
+                        Some(node) => {
+                            allocator.list_heads[index] = node.next.take();
+                            node as *mut ListNode as *mut u8
+                        }
+                        None => {
+                            // no block exists in list => allocate new block
+                            let block_size = BLOCK_SIZES[index];
+                            // only works if all block sizes are a power of 2
+                            let block_align = block_size;
+                            let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                            allocator.fallback_alloc(layout)
+                        }
                     }
                 }
-            }
 
-            None => allocator.fallback_alloc(layout)
-        }
+                None => allocator.fallback_alloc(layout)
+            }
+        })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let mut allocator = self.lock();
-        match list_index(&layout) {
-            Some(index) => {
-                let new_node = ListNode {
-                    next: allocator.list_heads[index].take(),
-                };
-                // verify that block has size and alignment required for storing node
-                assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
-                assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
-                let new_node_ptr = ptr as *mut ListNode;
-                new_node_ptr.write(new_node);
-                allocator.list_heads[index] = Some(&mut *new_node_ptr);
-            }
-            None => {
-                // Unwrap here because it fails only if the pointer is null
-                // which the compiler should always avoid
-                let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout);
+        interrupts::without_interrupts(|| {
+            let mut allocator = self.lock();
+            match list_index(&layout) {
+                Some(index) => {
+                    let new_node = ListNode {
+                        next: allocator.list_heads[index].take(),
+                    };
+                    // verify that block has size and alignment required for storing node
+                    assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+                    assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+                    let new_node_ptr = ptr as *mut ListNode;
+                    new_node_ptr.write(new_node);
+                    allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                }
+                None => {
+                    // Unwrap here because it fails only if the pointer is null
+                    // which the compiler should always avoid
+                    let ptr = NonNull::new(ptr).unwrap();
+                    allocator.fallback_allocator.deallocate(ptr, layout);
+                }
             }
-        }
+        })
     }
 }
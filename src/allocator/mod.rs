@@ -9,7 +9,7 @@ use self::fixed_size_block::FixedSizeBlockAllocator;
 pub mod fixed_size_block;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+pub const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB, the GUI's back buffer takes 3
 
 #[global_allocator]
 pub static ALLOCATOR: Locked<FixedSizeBlockAllocator> = 
@@ -0,0 +1,191 @@
+//! Local APIC of each CPU.
+//!
+//! The registers of every CPU's local APIC are at the same physical address
+//! and each CPU accesses its own there, so a single mapping is shared. The
+//! bootstrap processor keeps getting its timer and keyboard interrupts from
+//! the PIC, the application processors use the local APIC timer instead.
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use x86_64::PhysAddr;
+
+use crate::interrupts;
+use crate::memory;
+
+/// Vector of the local APIC timer interrupt
+pub const TIMER_VECTOR: u8 = 0x40;
+
+/// Vector of the spurious interrupts, they must not be acknowledged
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// Register offsets
+const ID: usize = 0x20;
+const EOI: usize = 0xb0;
+const SPURIOUS_INTERRUPT_VECTOR: usize = 0xf0;
+const ERROR_STATUS: usize = 0x280;
+const INTERRUPT_COMMAND_LOW: usize = 0x300;
+const INTERRUPT_COMMAND_HIGH: usize = 0x310;
+const LVT_TIMER: usize = 0x320;
+const TIMER_INITIAL_COUNT: usize = 0x380;
+const TIMER_CURRENT_COUNT: usize = 0x390;
+const TIMER_DIVIDE_CONFIGURATION: usize = 0x3e0;
+
+/// Size of the register space
+const REGISTERS_SIZE: u64 = 0x400;
+
+/// Bit of `SPURIOUS_INTERRUPT_VECTOR` that enables the local APIC
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+/// `LVT_TIMER` mode
+const TIMER_PERIODIC: u32 = 1 << 17;
+/// `TIMER_DIVIDE_CONFIGURATION` value to divide the bus clock by 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// Interrupt command delivery modes
+const DELIVERY_FIXED: u32 = 0b000 << 8;
+const DELIVERY_INIT: u32 = 0b101 << 8;
+const DELIVERY_STARTUP: u32 = 0b110 << 8;
+/// Set while an interrupt command is being sent
+const DELIVERY_PENDING: u32 = 1 << 12;
+const LEVEL_ASSERT: u32 = 1 << 14;
+/// Interrupt command destination shorthand, the APIC ID is ignored
+const ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+
+/// Virtual address of the registers, 0 until `init`
+static REGISTERS: AtomicU64 = AtomicU64::new(0);
+
+/// Initial count of the timer for `interrupts::TIMER_FREQUENCY`, measured
+/// by `init`
+static TIMER_INITIAL: AtomicU32 = AtomicU32::new(0);
+
+/// Maps and enables the local APIC of the bootstrap processor, and measures
+/// its timer against the PIT.
+///
+/// Needs interrupts enabled and `memory::init_kernel_memory`.
+pub fn init(address: PhysAddr) {
+    let registers = memory::map_mmio(address, REGISTERS_SIZE);
+    REGISTERS.store(registers.as_u64(), Ordering::Relaxed);
+    enable();
+
+    // count down from the maximum during a few PIT ticks
+    const CALIBRATION_TICKS: u32 = 5;
+    unsafe {
+        write(TIMER_DIVIDE_CONFIGURATION, TIMER_DIVIDE_BY_16);
+        wait_ticks(1);
+        write(TIMER_INITIAL_COUNT, u32::MAX);
+        wait_ticks(CALIBRATION_TICKS as u64);
+        let elapsed = u32::MAX - read(TIMER_CURRENT_COUNT);
+        write(TIMER_INITIAL_COUNT, 0);
+        TIMER_INITIAL.store(elapsed / CALIBRATION_TICKS, Ordering::Relaxed);
+    }
+}
+
+/// Enables the local APIC of an application processor, and starts its
+/// timer at `interrupts::TIMER_FREQUENCY`.
+pub(crate) fn init_ap() {
+    enable();
+    unsafe {
+        write(TIMER_DIVIDE_CONFIGURATION, TIMER_DIVIDE_BY_16);
+        write(LVT_TIMER, TIMER_PERIODIC | TIMER_VECTOR as u32);
+        write(TIMER_INITIAL_COUNT, TIMER_INITIAL.load(Ordering::Relaxed));
+    }
+}
+
+fn enable() {
+    unsafe {
+        write(SPURIOUS_INTERRUPT_VECTOR, SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+}
+
+/// Returns the APIC ID of the running CPU.
+pub fn id() -> u32 {
+    unsafe { read(ID) >> 24 }
+}
+
+/// Acknowledges the interrupt being handled.
+pub fn end_of_interrupt() {
+    unsafe { write(EOI, 0) };
+}
+
+/// Sends the interrupt `vector` to the CPU `apic_id`.
+pub fn send_ipi(apic_id: u32, vector: u8) {
+    send_command(apic_id, DELIVERY_FIXED | LEVEL_ASSERT | vector as u32);
+}
+
+/// Sends the interrupt `vector` to all the other CPUs, including the ones
+/// that are not started.
+pub fn broadcast_ipi(vector: u8) {
+    send_command(0, ALL_EXCLUDING_SELF | DELIVERY_FIXED | LEVEL_ASSERT | vector as u32);
+}
+
+/// Sends an INIT interrupt, that resets the CPU `apic_id` into the
+/// wait-for-SIPI state.
+pub(crate) fn send_init(apic_id: u32) {
+    send_command(apic_id, DELIVERY_INIT | LEVEL_ASSERT);
+}
+
+/// Sends a startup interrupt, the CPU `apic_id` starts in real mode at
+/// address `page << 12`.
+pub(crate) fn send_startup(apic_id: u32, page: u8) {
+    send_command(apic_id, DELIVERY_STARTUP | LEVEL_ASSERT | page as u32);
+}
+
+fn send_command(apic_id: u32, command: u32) {
+    // an interrupt handler sending its own command would overwrite ours
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        write(ERROR_STATUS, 0);
+        write(INTERRUPT_COMMAND_HIGH, apic_id << 24);
+        // writing the low half sends the interrupt
+        write(INTERRUPT_COMMAND_LOW, command);
+        while read(INTERRUPT_COMMAND_LOW) & DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    });
+}
+
+/// Waits for `ticks` timer interrupts of the bootstrap processor.
+pub(crate) fn wait_ticks(ticks: u64) {
+    let deadline = interrupts::ticks() + ticks;
+    while interrupts::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Waits at least `millis` milliseconds, with the resolution of the
+/// timer interrupt of the bootstrap processor.
+pub(crate) fn wait_millis(millis: u64) {
+    let period = 1000 / interrupts::TIMER_FREQUENCY as u64;
+    // the first tick may come right away
+    wait_ticks(millis.div_ceil(period) + 1);
+}
+
+/// Waits at least `micros` microseconds, counting down the APIC timer.
+///
+/// Only on the bootstrap processor, whose timer `init` leaves stopped and
+/// masked.
+pub(crate) fn wait_micros(micros: u64) {
+    let per_second = TIMER_INITIAL.load(Ordering::Relaxed) as u64
+        * interrupts::TIMER_FREQUENCY as u64;
+    let count = (micros * per_second).div_ceil(1_000_000).clamp(1, u32::MAX as u64);
+    unsafe {
+        write(TIMER_INITIAL_COUNT, count as u32);
+        while read(TIMER_CURRENT_COUNT) != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Safety: `register` must be a readable register offset, after `init`.
+unsafe fn read(register: usize) -> u32 {
+    let base = REGISTERS.load(Ordering::Relaxed);
+    debug_assert!(base != 0, "local APIC not initialized");
+    read_volatile((base as usize + register) as *const u32)
+}
+
+/// Safety: `register` must be a writable register offset, after `init`.
+unsafe fn write(register: usize, value: u32) {
+    let base = REGISTERS.load(Ordering::Relaxed);
+    debug_assert!(base != 0, "local APIC not initialized");
+    write_volatile((base as usize + register) as *mut u32, value);
+}
+
@@ -0,0 +1,32 @@
+//! Displays of character cells, that the `print!` macros write to.
+//!
+//! `vga_buffer::Writer` does the printing, and the virtual terminal shown
+//! draws on the `Console` set with `vga_buffer::set_console`: the VGA text
+//! mode buffer at boot, or a `framebuffer::FramebufferConsole`.
+
+use crate::vga_buffer::ScreenChar;
+
+pub trait Console: Send {
+    /// Number of columns
+    fn width(&self) -> usize;
+
+    /// Number of rows
+    fn height(&self) -> usize;
+
+    fn read(&self, row: usize, column: usize) -> ScreenChar;
+
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar);
+
+    /// Shows the cursor at `(row, column)`, or hides it with `None`.
+    fn set_cursor(&mut self, position: Option<(usize, usize)>);
+
+    /// Moves every row up by one, the last one staying as it is.
+    fn scroll(&mut self) {
+        for row in 1..self.height() {
+            for column in 0..self.width() {
+                let c = self.read(row, column);
+                self.write(row - 1, column, c);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Text console drawn with a bitmap font on the framebuffer.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{Font, Framebuffer};
+use crate::console::Console;
+use crate::vga_buffer::{ScreenChar, NORMAL_COLOR};
+
+/// Rows of pixels of the underline cursor, from the bottom of the cell
+const CURSOR_HEIGHT: usize = 2;
+
+/// Console of as many character cells as fit on the framebuffer
+pub struct FramebufferConsole {
+    framebuffer: Framebuffer,
+    font: Font,
+    /// Number of columns
+    width: usize,
+    /// Number of rows
+    height: usize,
+    /// Characters shown, row after row
+    cells: Vec<ScreenChar>,
+    cursor: Option<(usize, usize)>,
+}
+
+impl FramebufferConsole {
+    /// Blanks `framebuffer` to write on it with `font`.
+    pub fn new(mut framebuffer: Framebuffer, font: Font) -> Self {
+        let width = framebuffer.width() / font.width();
+        let height = framebuffer.height() / font.height();
+        let blank = ScreenChar::new(b' ', NORMAL_COLOR);
+        let (screen_width, screen_height) = (framebuffer.width(), framebuffer.height());
+        framebuffer.fill_rect(0, 0, screen_width, screen_height, blank.background);
+        Self {
+            framebuffer,
+            font,
+            width,
+            height,
+            cells: vec![blank; width * height],
+            cursor: None,
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Draws every cell again, after something else drew on the
+    /// framebuffer.
+    pub fn redraw(&mut self) {
+        for row in 0..self.height {
+            for column in 0..self.width {
+                self.draw(row, column);
+            }
+        }
+    }
+
+    /// Draws the cell at `(row, column)`, with the cursor if it is there.
+    fn draw(&mut self, row: usize, column: usize) {
+        let c = self.cells[row * self.width + column];
+        let (text, background) = (c.text, c.background);
+        let cursor = self.cursor == Some((row, column));
+        let (font_width, font_height) = (self.font.width(), self.font.height());
+        for y in 0..font_height {
+            let underline = cursor && y >= font_height - CURSOR_HEIGHT;
+            for x in 0..font_width {
+                let color = match underline || self.font.pixel(c.ascii_character, x, y) {
+                    true => text,
+                    false => background,
+                };
+                self.framebuffer.set_pixel(column * font_width + x, row * font_height + y, color);
+            }
+        }
+    }
+}
+
+impl Console for FramebufferConsole {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read(&self, row: usize, column: usize) -> ScreenChar {
+        self.cells[row * self.width + column]
+    }
+
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar) {
+        // only draws the cells that change, which also makes scrolling cheap
+        let cell = &mut self.cells[row * self.width + column];
+        if *cell != c {
+            *cell = c;
+            self.draw(row, column);
+        }
+    }
+
+    fn set_cursor(&mut self, position: Option<(usize, usize)>) {
+        let previous = core::mem::replace(&mut self.cursor, position);
+        if previous != position {
+            for (row, column) in previous.into_iter().chain(position) {
+                self.draw(row, column);
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! PSF2 bitmap fonts.
+//!
+//! The embedded `font.psf` has the 256 glyphs of code page 437 in order, so
+//! that the characters of `ScreenChar`s are directly glyph indices. It was
+//! rendered at 8x16 from DejaVu Sans Mono (Bitstream Vera license), with
+//! the box drawing and block characters drawn to fill the cells. Its
+//! copyright and license are in `font.psf.LICENSE`.
+
+const MAGIC: u32 = 0x864a_b572;
+const HEADER_SIZE: usize = 32;
+
+/// The font of the framebuffer console
+static DEFAULT_FONT: &[u8] = include_bytes!("font.psf");
+
+/// Glyphs of a PSF2 font, one bit per pixel, rows padded to whole bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Font {
+    width: usize,
+    height: usize,
+    glyphs: &'static [u8],
+    glyph_size: usize,
+    count: usize,
+}
+
+impl Font {
+    /// Parses the PSF2 font in `bytes`, the unicode table is ignored.
+    pub fn parse(bytes: &'static [u8]) -> Option<Self> {
+        let field = |index: usize| -> Option<u32> {
+            let bytes = bytes.get(index * 4..index * 4 + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().ok()?))
+        };
+        if field(0)? != MAGIC {
+            return None;
+        }
+        let header_size = field(2)? as usize;
+        let count = field(4)? as usize;
+        let glyph_size = field(5)? as usize;
+        let height = field(6)? as usize;
+        let width = field(7)? as usize;
+        if header_size < HEADER_SIZE || glyph_size < height * width.div_ceil(8) {
+            return None;
+        }
+        let glyphs = bytes.get(header_size..header_size + count * glyph_size)?;
+        Some(Self { width, height, glyphs, glyph_size, count })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns whether the pixel at `(x, y)` of glyph `index` is set, a
+    /// missing glyph being blank.
+    pub fn pixel(&self, index: u8, x: usize, y: usize) -> bool {
+        if index as usize >= self.count {
+            return false;
+        }
+        let row = index as usize * self.glyph_size + y * self.width.div_ceil(8);
+        self.glyphs[row + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font::parse(DEFAULT_FONT).expect("invalid embedded font")
+    }
+}
+
+#[test_case]
+fn test_default_font() {
+    let font = Font::default();
+    assert_eq!((font.width(), font.height()), (8, 16));
+    let pixels = |index| (0..16)
+        .flat_map(|y| (0..8).map(move |x| (x, y)))
+        .filter(|&(x, y)| font.pixel(index, x, y))
+        .count();
+    assert_eq!(pixels(b' '), 0);
+    assert!(pixels(b'A') > 10);
+    // full block
+    assert_eq!(pixels(0xdb), 8 * 16);
+}
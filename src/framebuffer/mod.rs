@@ -0,0 +1,198 @@
+//! Linear framebuffer of the Bochs and QEMU display adapters, and a text
+//! console drawn on it.
+//!
+//! `init` switches the adapter from VGA text mode to a true color graphics
+//! mode through its Bochs graphics adapter (BGA) registers. `init_console`
+//! then prints to it instead of the VGA text buffer, which stays in use on
+//! machines without such an adapter.
+
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use crate::graphics::Target;
+use crate::{memory, pci, vga_buffer};
+
+mod console;
+mod font;
+
+pub use self::console::FramebufferConsole;
+pub use self::font::Font;
+pub use crate::graphics::Rgb;
+
+/// Size of the screen set by `init_console`
+pub const DEFAULT_WIDTH: usize = 1024;
+pub const DEFAULT_HEIGHT: usize = 768;
+
+/// BGA ports, the index of a register then its value
+const BGA_INDEX_PORT: u16 = 0x01ce;
+const BGA_DATA_PORT: u16 = 0x01cf;
+
+/// BGA registers
+const BGA_ID: u16 = 0;
+const BGA_WIDTH: u16 = 1;
+const BGA_HEIGHT: u16 = 2;
+const BGA_BITS_PER_PIXEL: u16 = 3;
+const BGA_ENABLE: u16 = 4;
+
+/// Bits of the enable register
+const BGA_ENABLED: u16 = 0x01;
+const BGA_LINEAR_FRAMEBUFFER: u16 = 0x40;
+
+/// BGA versions with 32 bits per pixel
+const BGA_VERSIONS: core::ops::RangeInclusive<u16> = 0xb0c2..=0xb0c5;
+
+const BITS_PER_PIXEL: u16 = 32;
+
+/// Vendor and device IDs of the adapters with BGA registers: QEMU and
+/// Bochs, then VirtualBox
+const ADAPTERS: [(u16, u16); 2] = [(0x1234, 0x1111), (0x80ee, 0xbeef)];
+
+/// Set once `init` switched to graphics mode
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No display adapter with BGA registers
+    NoAdapter,
+    /// The adapter does not support this width and height
+    UnsupportedMode(usize, usize),
+    /// `init` was called already
+    AlreadyInitialized,
+}
+
+/// The screen, as 32 bit pixels
+pub struct Framebuffer {
+    pixels: *mut u32,
+    width: usize,
+    height: usize,
+}
+
+// the pixels are only reachable through the `Framebuffer` that `init` returns
+unsafe impl Send for Framebuffer {}
+
+/// Switches to graphics mode with `width` by `height` pixels, and returns
+/// the framebuffer. Needs `memory::init_kernel_memory`.
+pub fn init(width: usize, height: usize) -> Result<Framebuffer, Error> {
+    let adapter = ADAPTERS.iter()
+        .find_map(|&(vendor, device)| pci::find(vendor, device))
+        .ok_or(Error::NoAdapter)?;
+    if !BGA_VERSIONS.contains(&read_bga(BGA_ID)) {
+        return Err(Error::NoAdapter);
+    }
+    let address = adapter.memory_bar(0).ok_or(Error::NoAdapter)?;
+    if INITIALIZED.swap(true, Ordering::AcqRel) {
+        return Err(Error::AlreadyInitialized);
+    }
+
+    write_bga(BGA_ENABLE, 0);
+    write_bga(BGA_WIDTH, width as u16);
+    write_bga(BGA_HEIGHT, height as u16);
+    write_bga(BGA_BITS_PER_PIXEL, BITS_PER_PIXEL);
+    write_bga(BGA_ENABLE, BGA_ENABLED | BGA_LINEAR_FRAMEBUFFER);
+    // the adapter keeps its previous mode when it does not support this one
+    if read_bga(BGA_WIDTH) as usize != width
+        || read_bga(BGA_HEIGHT) as usize != height
+        || read_bga(BGA_BITS_PER_PIXEL) != BITS_PER_PIXEL
+    {
+        write_bga(BGA_ENABLE, 0);
+        INITIALIZED.store(false, Ordering::Release);
+        return Err(Error::UnsupportedMode(width, height));
+    }
+
+    let size = (width * height * 4) as u64;
+    let pixels = memory::map_mmio(address, size).as_mut_ptr();
+    Ok(Framebuffer { pixels, width, height })
+}
+
+/// Switches to graphics mode, and prints to it from now on.
+///
+/// Needs the heap and `memory::init_kernel_memory`.
+pub fn init_console() -> Result<(), Error> {
+    let framebuffer = init(DEFAULT_WIDTH, DEFAULT_HEIGHT)?;
+    let console = FramebufferConsole::new(framebuffer, Font::default());
+    vga_buffer::set_console(Box::leak(Box::new(console)));
+    Ok(())
+}
+
+fn read_bga(register: u16) -> u16 {
+    let mut index: Port<u16> = Port::new(BGA_INDEX_PORT);
+    let mut data: Port<u16> = Port::new(BGA_DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.read()
+    }
+}
+
+fn write_bga(register: u16, value: u16) {
+    let mut index: Port<u16> = Port::new(BGA_INDEX_PORT);
+    let mut data: Port<u16> = Port::new(BGA_DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.write(value);
+    }
+}
+
+impl Framebuffer {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns another `Framebuffer` on the same pixels.
+    ///
+    /// # Safety
+    /// Only one of them may be drawn on at a time.
+    pub(crate) unsafe fn alias(&self) -> Framebuffer {
+        Framebuffer { pixels: self.pixels, width: self.width, height: self.height }
+    }
+
+    /// Returns the color of the pixel at `(x, y)`, black outside of the
+    /// screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        if x >= self.width || y >= self.height {
+            return Rgb::default();
+        }
+        let pixel = unsafe { self.pixels.add(y * self.width + x).read_volatile() };
+        Rgb::from_pixel(pixel)
+    }
+
+    /// Sets the pixel at `(x, y)` to `color`, if it is on the screen.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            unsafe { self.pixels.add(y * self.width + x).write_volatile(color.to_pixel()) };
+        }
+    }
+
+    /// Fills the part of the rectangle at `(x, y)` of `width` by `height`
+    /// pixels that is on the screen.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+impl Target for Framebuffer {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Rgb {
+        Framebuffer::pixel(self, x, y)
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        Framebuffer::set_pixel(self, x, y, color);
+    }
+}
@@ -2,19 +2,24 @@
 ///! Defines a different stack for double fault handling to 
 ///! prevent triple faults
 
+use alloc::boxed::Box;
+use alloc::vec;
 use lazy_static::lazy_static;
 use x86_64::instructions::tables::load_tss;
-use x86_64::registers::segmentation::{CS, Segment};
+use x86_64::registers::segmentation::{CS, SS, Segment};
+use x86_64::PrivilegeLevel;
 use x86_64::{structures::tss::TaskStateSegment, VirtAddr};
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+/// Size of the double fault stacks
+const STACK_SIZE: usize = 4096 * 5;
+
 lazy_static!{
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
             static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
             let stack_start = VirtAddr::from_ptr(unsafe {&STACK});
@@ -39,10 +44,33 @@ struct Selectors {
     tss_selector: SegmentSelector,
 }
 
+/// Loads the GDT and TSS of the bootstrap processor.
 pub fn init() {
-    GDT.0.load();
+    load(&GDT.0, &GDT.1);
+}
+
+/// Loads a new GDT and TSS on an application processor, with its own
+/// double fault stack, since a TSS can only be used by one CPU at a time.
+///
+/// Needs the heap, the tables are never freed.
+pub fn init_ap() {
+    let stack = Box::leak(vec![0u8; STACK_SIZE].into_boxed_slice());
+    let tss = Box::leak(Box::new(TaskStateSegment::new()));
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+        VirtAddr::from_ptr(stack.as_ptr()) + STACK_SIZE;
+
+    let gdt = Box::leak(Box::new(GlobalDescriptorTable::new()));
+    let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+    load(gdt, &Selectors { code_selector, tss_selector });
+}
+
+fn load(gdt: &'static GlobalDescriptorTable, selectors: &Selectors) {
+    gdt.load();
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(selectors.code_selector);
+        // the GDT has no data segment, interrupts return to a null one
+        SS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        load_tss(selectors.tss_selector);
     }
 }
@@ -0,0 +1,265 @@
+//! Drawing primitives.
+
+use super::{Rect, Rgb, Rgba, Target};
+
+/// Pixels to draw with their opacity, row after row
+#[derive(Debug, Clone, Copy)]
+pub struct Bitmap<'a> {
+    width: usize,
+    height: usize,
+    pixels: &'a [Rgba],
+}
+
+/// Draws on a target, only inside of its clipping rectangle
+///
+/// Coordinates are signed so that shapes can be partly off the target.
+pub struct Canvas<'a, T: Target + ?Sized> {
+    target: &'a mut T,
+    clip: Rect,
+}
+
+impl<'a> Bitmap<'a> {
+    /// Panics if there are not `width * height` pixels.
+    pub fn new(width: usize, height: usize, pixels: &'a [Rgba]) -> Self {
+        assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+        Self { width, height, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl<'a, T: Target + ?Sized> Canvas<'a, T> {
+    /// Draws anywhere on `target`.
+    pub fn new(target: &'a mut T) -> Self {
+        let clip = Rect::new(0, 0, target.width() as u32, target.height() as u32);
+        Self { target, clip }
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Only draws inside of `rect` from now on, within the previous clipping
+    /// rectangle.
+    pub fn set_clip(&mut self, rect: Rect) {
+        self.clip = self.clip.intersection(rect);
+    }
+
+    /// Calls `f` with drawing only inside of `rect` too, then restores the
+    /// clipping rectangle.
+    pub fn with_clip<R>(&mut self, rect: Rect, f: impl FnOnce(&mut Self) -> R) -> R {
+        let clip = self.clip;
+        self.set_clip(rect);
+        let result = f(self);
+        self.clip = clip;
+        result
+    }
+
+    pub fn target(&self) -> &T {
+        &*self.target
+    }
+
+    /// Fills the whole clipping rectangle.
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(self.clip, color);
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Rgb) {
+        if self.mark(Rect::new(x, y, 1, 1)) {
+            self.plot(x, y, color);
+        }
+    }
+
+    /// Draws the line from `(x0, y0)` to `(x1, y1)`, both included, with
+    /// Bresenham's algorithm.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Rgb) {
+        if !self.mark(Rect::from_corners(x0, y0, x1, y1)) {
+            return;
+        }
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let double = 2 * error;
+            if double >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if double <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the outline of `rect`, inside of it.
+    pub fn draw_rect(&mut self, rect: Rect, color: Rgb) {
+        if rect.is_empty() || !self.mark(rect) {
+            return;
+        }
+        let (right, bottom) = (rect.right() - 1, rect.bottom() - 1);
+        self.span(rect.x, right, rect.y, color);
+        self.span(rect.x, right, bottom, color);
+        for y in rect.y + 1..bottom {
+            self.plot(rect.x, y, color);
+            self.plot(right, y, color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Rgb) {
+        if !self.mark(rect) {
+            return;
+        }
+        for y in rect.y..rect.bottom() {
+            self.span(rect.x, rect.right() - 1, y, color);
+        }
+    }
+
+    /// Draws the outline of the circle centered on `(x, y)`, with the
+    /// midpoint algorithm.
+    pub fn draw_circle(&mut self, x: i32, y: i32, radius: u32, color: Rgb) {
+        let r = radius as i32;
+        if !self.mark(Rect::from_corners(x - r, y - r, x + r, y + r)) {
+            return;
+        }
+        let (mut dx, mut dy) = (r, 0);
+        let mut error = 1 - r;
+        while dy <= dx {
+            for (px, py) in [(dx, dy), (dy, dx), (-dy, dx), (-dx, dy)] {
+                self.plot(x + px, y + py, color);
+                self.plot(x - px, y - py, color);
+            }
+            dy += 1;
+            if error < 0 {
+                error += 2 * dy + 1;
+            } else {
+                dx -= 1;
+                error += 2 * (dy - dx) + 1;
+            }
+        }
+    }
+
+    /// Fills the disc centered on `(x, y)`.
+    pub fn fill_circle(&mut self, x: i32, y: i32, radius: u32, color: Rgb) {
+        let r = radius as i32;
+        if !self.mark(Rect::from_corners(x - r, y - r, x + r, y + r)) {
+            return;
+        }
+        // the half width only shrinks going away from the center
+        let mut dx = r;
+        for dy in 0..=r {
+            while dx * dx + dy * dy > r * r + r {
+                dx -= 1;
+            }
+            self.span(x - dx, x + dx, y + dy, color);
+            if dy != 0 {
+                self.span(x - dx, x + dx, y - dy, color);
+            }
+        }
+    }
+
+    /// Fills the triangle with these corners, in any order: the pixels on
+    /// the inside of its three edges.
+    pub fn fill_triangle(&mut self, corners: [(i32, i32); 3], color: Rgb) {
+        let [a, b, c] = corners;
+        let bounds = Rect::from_corners(a.0, a.1, b.0, b.1)
+            .union(Rect::from_corners(c.0, c.1, c.0, c.1));
+        if !self.mark(bounds) {
+            return;
+        }
+        // which side of the edge from `p` to `q` the point is on
+        let side = |p: (i32, i32), q: (i32, i32), x: i32, y: i32| {
+            (q.0 - p.0) as i64 * (y - p.1) as i64 - (q.1 - p.1) as i64 * (x - p.0) as i64
+        };
+        let area = bounds.intersection(self.clip);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                let sides = [side(a, b, x, y), side(b, c, x, y), side(c, a, x, y)];
+                if sides.iter().all(|&s| s >= 0) || sides.iter().all(|&s| s <= 0) {
+                    self.plot(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Sets the pixels of `rect` for which `mask` returns `true`, given their
+    /// position in the rectangle.
+    pub fn fill_mask(&mut self, rect: Rect, color: Rgb, mask: impl Fn(usize, usize) -> bool) {
+        if !self.mark(rect) {
+            return;
+        }
+        let area = rect.intersection(self.clip);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                if mask((x - rect.x) as usize, (y - rect.y) as usize) {
+                    self.target.set_pixel(x as usize, y as usize, color);
+                }
+            }
+        }
+    }
+
+    /// Draws `bitmap` with its top left corner at `(x, y)`, blending the
+    /// pixels that are not opaque with what is below.
+    pub fn blit(&mut self, bitmap: &Bitmap, x: i32, y: i32) {
+        let bounds = Rect::new(x, y, bitmap.width as u32, bitmap.height as u32);
+        if !self.mark(bounds) {
+            return;
+        }
+        let area = bounds.intersection(self.clip);
+        for py in area.y..area.bottom() {
+            for px in area.x..area.right() {
+                let index = (py - y) as usize * bitmap.width + (px - x) as usize;
+                let color = bitmap.pixels[index];
+                match color.alpha {
+                    0 => {}
+                    255 => self.plot(px, py, Rgb::new(color.red, color.green, color.blue)),
+                    _ => {
+                        let below = self.target.pixel(px as usize, py as usize);
+                        self.plot(px, py, below.blend(color));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tells the target about a drawing inside of `rect`, returns whether
+    /// any of it is inside of the clipping rectangle.
+    fn mark(&mut self, rect: Rect) -> bool {
+        let rect = rect.intersection(self.clip);
+        if rect.is_empty() {
+            return false;
+        }
+        self.target.mark_dirty(rect);
+        true
+    }
+
+    /// Sets a pixel that is inside of the clipping rectangle.
+    fn plot(&mut self, x: i32, y: i32, color: Rgb) {
+        if self.clip.contains(x, y) {
+            self.target.set_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    /// Sets the pixels from `(x0, y)` to `(x1, y)` included.
+    fn span(&mut self, x0: i32, x1: i32, y: i32, color: Rgb) {
+        if y < self.clip.y || y >= self.clip.bottom() {
+            return;
+        }
+        for x in x0.max(self.clip.x)..=x1.min(self.clip.right() - 1) {
+            self.target.set_pixel(x as usize, y as usize, color);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Drawing off-screen, then showing what changed at once.
+
+use alloc::vec::Vec;
+
+use super::{Rect, Rgb, Surface, Target};
+
+/// Number of dirty rectangles kept apart before they are merged into one
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// Target that draws on a `Surface`, and copies the rectangles drawn on to
+/// `front` when flushed
+pub struct DoubleBuffer<T: Target> {
+    back: Surface,
+    front: T,
+    /// Rectangles that changed since the last flush, none overlapping or
+    /// sharing an edge
+    dirty: Vec<Rect>,
+}
+
+impl<T: Target> DoubleBuffer<T> {
+    /// Starts from a black back buffer, which the first flush shows whole.
+    pub fn new(front: T) -> Self {
+        let back = Surface::new(front.width(), front.height());
+        Self::with_back(front, back)
+    }
+
+    /// Draws on `back`, allocated beforehand, e.g. with `Surface::try_new`.
+    ///
+    /// Panics if it is not the size of `front`.
+    pub fn with_back(front: T, back: Surface) -> Self {
+        assert_eq!(
+            (back.width(), back.height()),
+            (front.width(), front.height()),
+            "back buffer of the wrong size"
+        );
+        let mut buffer = Self { back, front, dirty: Vec::new() };
+        buffer.mark_dirty(Rect::new(0, 0, buffer.width() as u32, buffer.height() as u32));
+        buffer
+    }
+
+    pub fn front(&self) -> &T {
+        &self.front
+    }
+
+    pub fn dirty_rects(&self) -> &[Rect] {
+        &self.dirty
+    }
+
+    /// Copies the rectangles that changed to `front`.
+    pub fn flush(&mut self) {
+        for rect in self.dirty.drain(..) {
+            for y in rect.y as usize..rect.bottom() as usize {
+                for x in rect.x as usize..rect.right() as usize {
+                    self.front.set_pixel(x, y, self.back.pixel(x, y));
+                }
+            }
+        }
+    }
+}
+
+impl<T: Target> Target for DoubleBuffer<T> {
+    fn width(&self) -> usize {
+        self.back.width()
+    }
+
+    fn height(&self) -> usize {
+        self.back.height()
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Rgb {
+        self.back.pixel(x, y)
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        self.back.set_pixel(x, y, color);
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        let screen = Rect::new(0, 0, self.width() as u32, self.height() as u32);
+        let mut rect = rect.intersection(screen);
+        if rect.is_empty() {
+            return;
+        }
+        // merging can make the rectangle touch ones it did not before
+        while let Some(index) = self.dirty.iter().position(|dirty| dirty.touches(rect)) {
+            rect = rect.union(self.dirty.swap_remove(index));
+        }
+        self.dirty.push(rect);
+        if self.dirty.len() > MAX_DIRTY_RECTS {
+            let all = self.dirty.drain(..).fold(Rect::default(), |all, rect| all.union(rect));
+            self.dirty.push(all);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Software 2D drawing.
+//!
+//! A `Canvas` draws pixels, lines, shapes and bitmaps on any `Target`,
+//! clipped to a rectangle. Targets are in-memory `Surface`s, the
+//! `Framebuffer`, and `DoubleBuffer`s, which draw on a `Surface` and only
+//! copy the rectangles that changed to the screen when flushed.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::vga_buffer::Color;
+
+mod canvas;
+mod double_buffer;
+mod rect;
+
+pub use self::canvas::{Bitmap, Canvas};
+pub use self::double_buffer::DoubleBuffer;
+pub use self::rect::Rect;
+
+/// A true color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// A true color with its opacity, from 0 for transparent to 255 for opaque
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgba {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: u8,
+}
+
+/// Something to draw on, made of `width` by `height` pixels
+pub trait Target {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    /// Returns the color of the pixel at `(x, y)`, black outside of the
+    /// target.
+    fn pixel(&self, x: usize, y: usize) -> Rgb;
+
+    /// Sets the pixel at `(x, y)` to `color`, if it is on the target.
+    fn set_pixel(&mut self, x: usize, y: usize, color: Rgb);
+
+    /// Called by `Canvas` with the rectangle of each drawing before it
+    /// draws, for targets that keep track of what changed.
+    fn mark_dirty(&mut self, _rect: Rect) {}
+}
+
+/// Pixels in memory, row after row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Surface {
+    width: usize,
+    height: usize,
+    /// 32 bit pixels, like the framebuffer's
+    pixels: Vec<u32>,
+}
+
+impl Rgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// The color from its 0xRRGGBB value
+    pub const fn from_hex(hex: u32) -> Self {
+        Self::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+    }
+
+    /// The color of a 32 bit pixel
+    pub(crate) fn from_pixel(pixel: u32) -> Self {
+        Self::from_hex(pixel)
+    }
+
+    pub(crate) fn to_pixel(self) -> u32 {
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
+
+    /// This color seen through `color`
+    pub fn blend(self, color: Rgba) -> Self {
+        let mix = |below: u8, above: u8| {
+            let alpha = color.alpha as u32;
+            ((above as u32 * alpha + below as u32 * (255 - alpha) + 127) / 255) as u8
+        };
+        Self::new(
+            mix(self.red, color.red),
+            mix(self.green, color.green),
+            mix(self.blue, color.blue),
+        )
+    }
+}
+
+impl From<Color> for Rgb {
+    /// The color of the VGA palette
+    fn from(color: Color) -> Self {
+        const PALETTE: [u32; 16] = [
+            0x000000, 0x0000aa, 0x00aa00, 0x00aaaa, 0xaa0000, 0xaa00aa, 0xaa5500, 0xaaaaaa,
+            0x555555, 0x5555ff, 0x55ff55, 0x55ffff, 0xff5555, 0xff55ff, 0xffff55, 0xffffff,
+        ];
+        Rgb::from_hex(PALETTE[color as usize])
+    }
+}
+
+impl Rgba {
+    pub const fn new(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Self { red, green, blue, alpha }
+    }
+
+    /// The color from its 0xAARRGGBB value
+    pub const fn from_hex(hex: u32) -> Self {
+        Self::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8, (hex >> 24) as u8)
+    }
+}
+
+impl From<Rgb> for Rgba {
+    /// The opaque color
+    fn from(color: Rgb) -> Self {
+        Self::new(color.red, color.green, color.blue, 255)
+    }
+}
+
+impl Surface {
+    /// A black surface
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// A black surface, or `None` if there is not enough memory for it,
+    /// for the large ones.
+    pub fn try_new(width: usize, height: usize) -> Option<Self> {
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(width * height).ok()?;
+        pixels.resize(width * height, 0);
+        Some(Self { width, height, pixels })
+    }
+
+    /// The pixels as 0xRRGGBB values
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    /// FNV-1a hash of the pixels, to compare drawings
+    pub fn checksum(&self) -> u32 {
+        self.pixels.iter()
+            .map(|&pixel| Rgb::from_pixel(pixel))
+            .flat_map(|pixel| [pixel.red, pixel.green, pixel.blue])
+            .fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+}
+
+impl Target for Surface {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Rgb {
+        if x >= self.width || y >= self.height {
+            return Rgb::default();
+        }
+        Rgb::from_pixel(self.pixels[y * self.width + x])
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color.to_pixel();
+        }
+    }
+}
+
+#[cfg(test)]
+const WHITE: Rgb = Rgb::from_hex(0xffffff);
+
+#[test_case]
+fn test_draw_shapes() {
+    let mut surface = Surface::new(64, 48);
+    let mut canvas = Canvas::new(&mut surface);
+    canvas.draw_line(0, 0, 63, 47, WHITE);
+    canvas.draw_line(60, 2, 3, 40, Rgb::from_hex(0x00ff00));
+    canvas.draw_rect(Rect::new(4, 4, 20, 10), Rgb::from_hex(0xff0000));
+    canvas.fill_rect(Rect::new(40, 30, 12, 8), Rgb::from_hex(0x0000ff));
+    canvas.draw_circle(32, 24, 10, Rgb::from_hex(0xffff00));
+    canvas.fill_circle(12, 34, 6, Rgb::from_hex(0x00ffff));
+    canvas.fill_triangle([(50, 2), (62, 20), (38, 14)], Rgb::from_hex(0xff00ff));
+    assert_eq!(surface.pixel(0, 0), WHITE);
+    assert_eq!(surface.pixel(63, 47), WHITE);
+    assert_eq!(surface.pixel(4, 13), Rgb::from_hex(0xff0000));
+    assert_eq!(surface.pixel(5, 12), Rgb::default());
+    assert_eq!(surface.checksum(), 0xb70e_71a1);
+}
+
+#[test_case]
+fn test_clipping() {
+    let mut surface = Surface::new(32, 32);
+    let mut canvas = Canvas::new(&mut surface);
+    canvas.set_clip(Rect::new(8, 8, 16, 16));
+    canvas.set_clip(Rect::new(-10, -10, 30, 30));
+    assert_eq!(canvas.clip(), Rect::new(8, 8, 12, 12));
+    canvas.clear(Rgb::from_hex(0x202020));
+    canvas.fill_circle(8, 8, 20, WHITE);
+    canvas.draw_line(-100, 19, 100, 19, Rgb::from_hex(0xff0000));
+    canvas.fill_triangle([(-50, -50), (50, 0), (0, 30)], Rgb::from_hex(0x00ff00));
+    let outside = surface.pixels().iter().enumerate()
+        .filter(|&(index, _)| !Rect::new(8, 8, 12, 12).contains(index as i32 % 32, index as i32 / 32))
+        .all(|(_, &pixel)| Rgb::from_pixel(pixel) == Rgb::default());
+    assert!(outside);
+    assert_eq!(surface.pixel(19, 19), Rgb::from_hex(0xff0000));
+    assert_eq!(surface.checksum(), 0xbf7e_e3d3);
+}
+
+#[test_case]
+fn test_blit_with_alpha() {
+    let red = Rgb::from_hex(0xff0000);
+    let pixels = [
+        Rgba::from_hex(0x00ffffff), Rgba::from_hex(0x800000ff), Rgba::from_hex(0xff00ff00),
+        Rgba::from_hex(0xff00ff00), Rgba::from_hex(0x40ffffff), Rgba::from_hex(0x00000000),
+    ];
+    let bitmap = Bitmap::new(3, 2, &pixels);
+    let mut surface = Surface::new(8, 8);
+    let mut canvas = Canvas::new(&mut surface);
+    canvas.clear(red);
+    canvas.blit(&bitmap, 2, 3);
+    canvas.blit(&bitmap, 6, -1);
+    assert_eq!(surface.pixel(2, 3), red);
+    assert_eq!(surface.pixel(3, 3), Rgb::new(0x7f, 0, 0x80));
+    assert_eq!(surface.pixel(4, 3), Rgb::from_hex(0x00ff00));
+    assert_eq!(surface.pixel(3, 4), Rgb::new(0xff, 0x40, 0x40));
+    assert_eq!(surface.checksum(), 0xd71f_8b73);
+}
+
+#[test_case]
+fn test_double_buffer_flushes_dirty_rects() {
+    let mut buffer = DoubleBuffer::new(Surface::new(40, 30));
+    assert_eq!(buffer.dirty_rects(), &[Rect::new(0, 0, 40, 30)]);
+    buffer.flush();
+    assert!(buffer.dirty_rects().is_empty());
+
+    let mut canvas = Canvas::new(&mut buffer);
+    canvas.fill_rect(Rect::new(2, 2, 5, 5), WHITE);
+    canvas.draw_line(30, 20, 35, 28, WHITE);
+    canvas.set_pixel(50, 50, WHITE);
+    assert_eq!(buffer.dirty_rects(), &[Rect::new(2, 2, 5, 5), Rect::new(30, 20, 6, 9)]);
+    assert_eq!(buffer.front().checksum(), Surface::new(40, 30).checksum());
+
+    // touching the first rectangle merges them
+    Canvas::new(&mut buffer).fill_rect(Rect::new(7, 2, 3, 1), WHITE);
+    assert_eq!(buffer.dirty_rects(), &[Rect::new(30, 20, 6, 9), Rect::new(2, 2, 8, 5)]);
+    buffer.flush();
+    assert_eq!(buffer.front().pixel(9, 2), WHITE);
+    assert_eq!(buffer.front().checksum(), 0x08b9_ef46);
+
+    // one more pixel apart than there are rectangles kept apart
+    for i in 0..17 {
+        Canvas::new(&mut buffer).set_pixel(i * 2, 0, WHITE);
+    }
+    assert_eq!(buffer.dirty_rects().len(), 1);
+}
@@ -0,0 +1,76 @@
+//! Rectangles of pixels.
+
+/// Rectangle of `width` by `height` pixels, with its top left corner at
+/// `(x, y)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// The smallest rectangle with both corners, included
+    pub fn from_corners(x0: i32, y0: i32, x1: i32, y1: i32) -> Self {
+        let (left, right) = (x0.min(x1), x0.max(x1));
+        let (top, bottom) = (y0.min(y1), y0.max(y1));
+        Self::new(left, top, (right - left) as u32 + 1, (bottom - top) as u32 + 1)
+    }
+
+    /// First column on the right of the rectangle
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    /// First row below the rectangle
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// The part of both rectangles, empty if they do not overlap
+    pub fn intersection(&self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return Rect::default();
+        }
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+
+    /// The smallest rectangle containing both, ignoring empty ones
+    pub fn union(&self, other: Rect) -> Rect {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+
+    /// Returns whether the rectangles overlap or share an edge.
+    pub fn touches(&self, other: Rect) -> bool {
+        !self.is_empty() && !other.is_empty()
+            && self.x <= other.right() && other.x <= self.right()
+            && self.y <= other.bottom() && other.y <= self.bottom()
+    }
+}
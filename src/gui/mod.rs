@@ -0,0 +1,638 @@
+//! Windows on the framebuffer, drawn by a compositor task.
+//!
+//! The `Compositor` keeps the windows from back to front, the last one
+//! having the focus. It moves the mouse cursor, raises and drags the windows
+//! clicked on, and gives the key events to the focused widget of the focused
+//! window. Only the damaged rectangles of the screen are drawn again, on a
+//! `DoubleBuffer` flushed once the pending events are handled.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use futures_util::{FutureExt, StreamExt};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::framebuffer::{self, Font, Framebuffer, FramebufferConsole};
+use crate::graphics::{Bitmap, Canvas, DoubleBuffer, Rect, Rgb, Rgba, Surface, Target};
+use crate::ps2::{self, Channel};
+use crate::task::keyboard::{self, KeyCode, KeyEvent, KeyEventStream, KeyState};
+use crate::task::mouse::{Buttons, MouseEvent, MouseStream};
+use crate::vga_buffer::{self, cp437};
+
+mod terminal;
+mod widget;
+mod window;
+
+pub use self::terminal::{Terminal, TerminalConsole};
+pub use self::widget::{Button, Label, TextBox, Widget};
+pub use self::window::Window;
+
+/// Size of the glyphs of the default font
+const FONT_WIDTH: u32 = 8;
+const FONT_HEIGHT: u32 = 16;
+
+/// Size of the console in the terminal window of `init`
+const TERMINAL_COLUMNS: usize = 80;
+const TERMINAL_ROWS: usize = 30;
+
+/// Damaged rectangles kept apart before they are merged into one
+const MAX_DAMAGE: usize = 16;
+
+const DESKTOP: Rgb = Rgb::from_hex(0x008080);
+
+/// The mouse cursor, its tip at the top left corner: `X` for the outline,
+/// `.` for the inside
+const CURSOR: [&[u8; CURSOR_WIDTH]; CURSOR_HEIGHT] = [
+    b"X          ",
+    b"XX         ",
+    b"X.X        ",
+    b"X..X       ",
+    b"X...X      ",
+    b"X....X     ",
+    b"X.....X    ",
+    b"X......X   ",
+    b"X.......X  ",
+    b"X........X ",
+    b"X.........X",
+    b"X......XXXX",
+    b"X...X..X   ",
+    b"X..XX..X   ",
+    b"X.X  X..X  ",
+    b"XX   X..X  ",
+    b"X     X..X ",
+    b"      XXXX ",
+];
+const CURSOR_WIDTH: usize = 11;
+const CURSOR_HEIGHT: usize = 18;
+
+static CURSOR_PIXELS: [Rgba; CURSOR_WIDTH * CURSOR_HEIGHT] = cursor_pixels();
+
+lazy_static! {
+    static ref FONT: Font = Font::default();
+}
+
+/// Console on the framebuffer of `init`, made beforehand for
+/// `take_over_console` which must not allocate
+///
+/// Only locked with interrupts disabled.
+static PANIC_CONSOLE: Mutex<Option<&'static mut FramebufferConsole>> = Mutex::new(None);
+
+/// Set by `take_over_console`, to stop the compositor
+static TAKEN_OVER: AtomicBool = AtomicBool::new(false);
+
+/// Event given to a widget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A key event, for the focused widget
+    Key(KeyEvent),
+    /// The left mouse button went down at `(x, y)` in the widget
+    Press { x: i32, y: i32 },
+    /// The left mouse button went up at `(x, y)`, relative to the widget it
+    /// went down on, which may be outside of it
+    Release { x: i32, y: i32 },
+}
+
+/// Identifies a window of a `Compositor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowId(u64);
+
+/// Draws windows on `T`, usually the `Framebuffer`
+pub struct Compositor<T: Target> {
+    screen: DoubleBuffer<T>,
+    /// From back to front, the last one having the focus
+    windows: Vec<(WindowId, Window)>,
+    next_id: u64,
+    cursor: (i32, i32),
+    buttons: Buttons,
+    grab: Option<Grab>,
+    /// Parts of the screen to draw again
+    damage: Vec<Rect>,
+}
+
+/// What the left mouse button went down on
+enum Grab {
+    /// A title bar, `(dx, dy)` away from the top left corner of the window
+    Title { id: WindowId, dx: i32, dy: i32 },
+    Widget { id: WindowId, index: usize },
+}
+
+enum Input {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    /// A rectangle of the screen a widget changed
+    Damage(Rect),
+}
+
+/// Why `init` stayed in text mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Framebuffer(framebuffer::Error),
+    /// Not enough memory for the back buffer
+    OutOfMemory,
+}
+
+impl From<framebuffer::Error> for Error {
+    fn from(err: framebuffer::Error) -> Self {
+        Error::Framebuffer(err)
+    }
+}
+
+/// Switches to graphics mode, with the console printing in a terminal
+/// window. The returned compositor must be run to show anything.
+///
+/// Needs the heap and `memory::init_kernel_memory`.
+pub fn init() -> Result<Compositor<Framebuffer>, Error> {
+    use framebuffer::{DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+    // allocated before switching modes, so that the text mode stays if
+    // there is not enough memory
+    let back = Surface::try_new(DEFAULT_WIDTH, DEFAULT_HEIGHT).ok_or(Error::OutOfMemory)?;
+    let (terminal, console) = Terminal::new(0, 0, TERMINAL_COLUMNS, TERMINAL_ROWS);
+    let rect = terminal.rect();
+    let about = Window::new("About", 720, 40, 260, 120)
+        .with(Label::new(10, 10, "Swag Kernel"))
+        .with(TextBox::new(Rect::new(10, 40, 240, 22)))
+        .with(Button::new(Rect::new(160, 80, 90, 26), "Clear", vga_buffer::clear_screen));
+    let terminal = Window::new("Terminal", 20, 20, rect.width, rect.height).with(terminal);
+    let console = Box::new(console);
+
+    let framebuffer = framebuffer::init(DEFAULT_WIDTH, DEFAULT_HEIGHT)?;
+    // the compositor stops drawing on it once `take_over_console` is called
+    let alias = unsafe { framebuffer.alias() };
+    let panic_console = Box::leak(Box::new(FramebufferConsole::new(alias, Font::default())));
+    interrupts::without_interrupts(|| *PANIC_CONSOLE.lock() = Some(panic_console));
+    let mut compositor = Compositor::with_screen(DoubleBuffer::with_back(framebuffer, back));
+    compositor.add_window(about);
+    compositor.add_window(terminal);
+    vga_buffer::set_console(Box::leak(console));
+    Ok(compositor)
+}
+
+/// Prints on the framebuffer directly from now on, instead of in the
+/// terminal window, and stops the compositor. For the panic handler: the
+/// compositor task may never be run again to draw what is printed.
+///
+/// Never allocates. Does nothing if `init` did not switch to graphics
+/// mode, or the second time.
+pub fn take_over_console() {
+    let console = interrupts::without_interrupts(|| PANIC_CONSOLE.lock().take());
+    if let Some(console) = console {
+        TAKEN_OVER.store(true, Ordering::Release);
+        console.redraw();
+        vga_buffer::replace_console(console);
+    }
+}
+
+/// Draws the text at `(x, y)` with the default font, on a single line.
+fn draw_text(canvas: &mut Canvas<'_, dyn Target + '_>, x: i32, y: i32, text: &str, color: Rgb) {
+    for (index, c) in text.chars().enumerate() {
+        let glyph = cp437::encode(c).unwrap_or(cp437::REPLACEMENT);
+        let rect = Rect::new(x + (index as u32 * FONT_WIDTH) as i32, y, FONT_WIDTH, FONT_HEIGHT);
+        canvas.fill_mask(rect, color, |x, y| FONT.pixel(glyph, x, y));
+    }
+}
+
+/// Width of `text` drawn by `draw_text`
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * FONT_WIDTH
+}
+
+const fn cursor_pixels() -> [Rgba; CURSOR_WIDTH * CURSOR_HEIGHT] {
+    let mut pixels = [Rgba::new(0, 0, 0, 0); CURSOR_WIDTH * CURSOR_HEIGHT];
+    let mut index = 0;
+    while index < pixels.len() {
+        pixels[index] = match CURSOR[index / CURSOR_WIDTH][index % CURSOR_WIDTH] {
+            b'X' => Rgba::from_hex(0xff000000),
+            b'.' => Rgba::from_hex(0xffffffff),
+            _ => Rgba::from_hex(0x00000000),
+        };
+        index += 1;
+    }
+    pixels
+}
+
+impl Grab {
+    fn window(&self) -> WindowId {
+        match *self {
+            Grab::Title { id, .. } | Grab::Widget { id, .. } => id,
+        }
+    }
+}
+
+impl<T: Target> Compositor<T> {
+    /// Shows an empty desktop on `target`, the mouse cursor in the middle.
+    pub fn new(target: T) -> Self {
+        Self::with_screen(DoubleBuffer::new(target))
+    }
+
+    /// Like `new`, drawing through `screen`.
+    pub fn with_screen(screen: DoubleBuffer<T>) -> Self {
+        let (width, height) = (screen.width(), screen.height());
+        Self {
+            screen,
+            windows: Vec::new(),
+            next_id: 0,
+            cursor: (width as i32 / 2, height as i32 / 2),
+            buttons: Buttons::default(),
+            grab: None,
+            damage: Vec::from([Rect::new(0, 0, width as u32, height as u32)]),
+        }
+    }
+
+    /// The screen, as of the last `render`
+    pub fn screen(&self) -> &T {
+        self.screen.front()
+    }
+
+    pub fn cursor(&self) -> (i32, i32) {
+        self.cursor
+    }
+
+    /// Shows `window` above the others, with the focus.
+    pub fn add_window(&mut self, window: Window) -> WindowId {
+        let id = WindowId(self.next_id);
+        self.next_id += 1;
+        if let Some((_, focused)) = self.windows.last() {
+            self.damage(focused.rect());
+        }
+        self.damage(window.rect());
+        self.windows.push((id, window));
+        id
+    }
+
+    pub fn remove_window(&mut self, id: WindowId) -> Option<Window> {
+        let index = self.position(id)?;
+        if self.grab.as_ref().is_some_and(|grab| grab.window() == id) {
+            self.grab = None;
+        }
+        let (_, window) = self.windows.remove(index);
+        self.damage(window.rect());
+        if let Some((_, focused)) = self.windows.last() {
+            self.damage(focused.rect());
+        }
+        Some(window)
+    }
+
+    pub fn window(&self, id: WindowId) -> Option<&Window> {
+        Some(&self.windows[self.position(id)?].1)
+    }
+
+    /// The window with the focus, above the others
+    pub fn focused(&self) -> Option<WindowId> {
+        self.windows.last().map(|&(id, _)| id)
+    }
+
+    /// Puts window `id` above the others, and gives it the focus.
+    pub fn raise(&mut self, id: WindowId) {
+        let Some(index) = self.position(id) else {
+            return;
+        };
+        if index + 1 == self.windows.len() {
+            return;
+        }
+        let window = self.windows.remove(index);
+        self.damage(window.1.rect());
+        if let Some((_, focused)) = self.windows.last() {
+            self.damage(focused.rect());
+        }
+        self.windows.push(window);
+    }
+
+    /// Gives `event` to the focused widget of the focused window. Tab gives
+    /// the focus to the next widget that takes it, if there is one.
+    pub fn handle_key(&mut self, event: KeyEvent) {
+        let Some(window) = self.windows.len().checked_sub(1) else {
+            return;
+        };
+        if event.code == KeyCode::Tab {
+            let redraw = match event.state {
+                KeyState::Down => self.windows[window].1.focus_next(),
+                _ => [None, None],
+            };
+            for index in redraw.into_iter().flatten() {
+                let rect = self.windows[window].1.widget_rect(index);
+                self.damage(rect);
+            }
+            if redraw[1].is_some() {
+                return;
+            }
+        }
+        if let Some(index) = self.windows[window].1.focus() {
+            self.send(window, index, Event::Key(event));
+        }
+    }
+
+    /// Moves the cursor, and handles the left button going down or up.
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        let (width, height) = (self.screen.width() as i32, self.screen.height() as i32);
+        let (x, y) = (
+            (self.cursor.0 + event.dx as i32).clamp(0, width - 1),
+            (self.cursor.1 + event.dy as i32).clamp(0, height - 1),
+        );
+        if (x, y) != self.cursor {
+            self.damage(self.cursor_rect());
+            self.cursor = (x, y);
+            self.damage(self.cursor_rect());
+            if let Some(Grab::Title { id, dx, dy }) = self.grab {
+                self.move_window(id, x - dx, y - dy);
+            }
+        }
+
+        let was_down = core::mem::replace(&mut self.buttons, event.buttons).left;
+        match (was_down, event.buttons.left) {
+            (false, true) => self.press(),
+            (true, false) => self.release(),
+            _ => {}
+        }
+    }
+
+    /// Draws the damaged parts of the screen, and shows them.
+    pub fn render(&mut self) {
+        let cursor = Bitmap::new(CURSOR_WIDTH, CURSOR_HEIGHT, &CURSOR_PIXELS);
+        let focused = self.windows.len().saturating_sub(1);
+        for area in core::mem::take(&mut self.damage) {
+            let mut canvas = Canvas::new(&mut self.screen as &mut dyn Target);
+            canvas.set_clip(area);
+            canvas.clear(DESKTOP);
+            for (index, (_, window)) in self.windows.iter().enumerate() {
+                if !window.rect().intersection(area).is_empty() {
+                    window.draw(&mut canvas, index == focused);
+                }
+            }
+            canvas.blit(&cursor, self.cursor.0, self.cursor.1);
+        }
+        self.screen.flush();
+    }
+
+    fn position(&self, id: WindowId) -> Option<usize> {
+        self.windows.iter().position(|&(window, _)| window == id)
+    }
+
+    fn cursor_rect(&self) -> Rect {
+        Rect::new(self.cursor.0, self.cursor.1, CURSOR_WIDTH as u32, CURSOR_HEIGHT as u32)
+    }
+
+    fn damage(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        self.damage.push(rect);
+        if self.damage.len() > MAX_DAMAGE {
+            let all = self.damage.drain(..).fold(Rect::default(), |all, rect| all.union(rect));
+            self.damage.push(all);
+        }
+    }
+
+    fn move_window(&mut self, id: WindowId, x: i32, y: i32) {
+        let Some(index) = self.position(id) else {
+            return;
+        };
+        let old = self.windows[index].1.rect();
+        self.windows[index].1.move_to(x, y);
+        self.damage(old);
+        self.damage(self.windows[index].1.rect());
+    }
+
+    /// Gives `event` to widget `index` of window `window`.
+    fn send(&mut self, window: usize, index: usize, event: Event) {
+        let window = &mut self.windows[window].1;
+        if window.widget_mut(index).handle(&event) {
+            let rect = window.widget_rect(index);
+            self.damage(rect);
+        }
+    }
+
+    /// Raises the window under the cursor, and grabs its title bar or
+    /// presses on its widget.
+    fn press(&mut self) {
+        let (x, y) = self.cursor;
+        let under = self.windows.iter().rposition(|(_, window)| window.rect().contains(x, y));
+        let Some(index) = under else {
+            return;
+        };
+        let id = self.windows[index].0;
+        self.raise(id);
+        let top = self.windows.len() - 1;
+        let window = &mut self.windows[top].1;
+        if window.title_bar().contains(x, y) {
+            let rect = window.rect();
+            self.grab = Some(Grab::Title { id, dx: x - rect.x, dy: y - rect.y });
+        } else if let Some(widget) = window.widget_at(x, y) {
+            let redraw = window.set_focus(widget);
+            let rect = window.widget_rect(widget);
+            for index in redraw.into_iter().flatten() {
+                let rect = self.windows[top].1.widget_rect(index);
+                self.damage(rect);
+            }
+            self.grab = Some(Grab::Widget { id, index: widget });
+            self.send(top, widget, Event::Press { x: x - rect.x, y: y - rect.y });
+        }
+    }
+
+    /// Releases what `press` grabbed.
+    fn release(&mut self) {
+        let Some(Grab::Widget { id, index }) = self.grab.take() else {
+            return;
+        };
+        if let Some(window) = self.position(id) {
+            let rect = self.windows[window].1.widget_rect(index);
+            let (x, y) = self.cursor;
+            self.send(window, index, Event::Release { x: x - rect.x, y: y - rect.y });
+        }
+    }
+
+    /// Returns the next input, or registers the waker of `cx` to be woken
+    /// when there is one.
+    fn poll_input(
+        &mut self,
+        cx: &mut Context<'_>,
+        keys: &mut KeyEventStream,
+        mouse: &mut Option<MouseStream>,
+    ) -> Poll<Input> {
+        if let Poll::Ready(Some(event)) = keys.poll_next_unpin(cx) {
+            return Poll::Ready(Input::Key(event));
+        }
+        if let Some(mouse) = mouse {
+            if let Poll::Ready(Some(event)) = mouse.poll_next_unpin(cx) {
+                return Poll::Ready(Input::Mouse(event));
+            }
+        }
+        for (_, window) in &mut self.windows {
+            for index in 0..window.widget_count() {
+                if let Poll::Ready(damage) = window.widget_mut(index).poll_damage(cx) {
+                    let rect = window.widget_rect(index);
+                    let damage = Rect { x: rect.x + damage.x, y: rect.y + damage.y, ..damage };
+                    return Poll::Ready(Input::Damage(damage.intersection(window.content())));
+                }
+            }
+        }
+        Poll::Pending
+    }
+
+    fn handle(&mut self, input: Input) {
+        match input {
+            Input::Key(event) => self.handle_key(event),
+            Input::Mouse(event) => self.handle_mouse(event),
+            Input::Damage(rect) => self.damage(rect),
+        }
+    }
+}
+
+impl<T: Target + Send> Compositor<T> {
+    /// Gives the key and mouse events to the windows and draws them,
+    /// forever.
+    ///
+    /// Needs `keyboard::run` to be spawned too. Must be spawned once, as
+    /// the only reader of the mouse.
+    pub async fn run(mut self) {
+        let mut keys = keyboard::subscribe();
+        let mut mouse = ps2::device(Channel::Second)
+            .filter(|device| device.is_mouse())
+            .map(|_| MouseStream::new());
+        self.render();
+
+        loop {
+            let input = poll_fn(|cx| self.poll_input(cx, &mut keys, &mut mouse)).await;
+            self.handle(input);
+            // drawn once for all the events already there
+            while let Some(input) =
+                poll_fn(|cx| self.poll_input(cx, &mut keys, &mut mouse)).now_or_never()
+            {
+                self.handle(input);
+            }
+            // the console draws on the framebuffer itself now
+            if TAKEN_OVER.load(Ordering::Acquire) {
+                return;
+            }
+            self.render();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use spin::Mutex;
+
+    use super::*;
+    use crate::task::keyboard::Modifiers;
+
+    /// Widget keeping the events it gets
+    struct Recorder {
+        rect: Rect,
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl Widget for Recorder {
+        fn rect(&self) -> Rect {
+            self.rect
+        }
+
+        fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, _focused: bool) {
+            canvas.fill_rect(rect, Rgb::from_hex(0xff0000));
+        }
+
+        fn handle(&mut self, event: &Event) -> bool {
+            self.events.lock().push(*event);
+            false
+        }
+
+        fn focusable(&self) -> bool {
+            true
+        }
+    }
+
+    fn recorder(rect: Rect) -> (Recorder, Arc<Mutex<Vec<Event>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        (Recorder { rect, events: events.clone() }, events)
+    }
+
+    fn key(code: KeyCode, unicode: Option<char>) -> KeyEvent {
+        KeyEvent { code, state: KeyState::Down, modifiers: Modifiers::default(), unicode }
+    }
+
+    /// Moves the mouse by `(dx, dy)`, with the left button `down` or not.
+    fn mouse(dx: i16, dy: i16, down: bool) -> MouseEvent {
+        let buttons = Buttons { left: down, ..Buttons::default() };
+        MouseEvent { dx, dy, dz: 0, buttons }
+    }
+
+    #[test_case]
+    fn test_windows_stack_and_raise() {
+        let mut compositor = Compositor::new(Surface::new(320, 240));
+        let back = compositor.add_window(Window::new("back", 10, 10, 100, 60));
+        let front = compositor.add_window(Window::new("front", 60, 40, 100, 60));
+        assert_eq!(compositor.focused(), Some(front));
+        compositor.render();
+        // the title bar of the front window covers the back one
+        let screen = compositor.screen();
+        assert_eq!(screen.pixel(108, 50), Rgb::from_hex(0x000080));
+        assert_eq!(screen.pixel(5, 5), DESKTOP);
+        // the cursor starts in the middle
+        assert_eq!(screen.pixel(160, 120), Rgb::from_hex(0x000000));
+        assert_eq!(screen.pixel(161, 122), Rgb::from_hex(0xffffff));
+
+        // clicking on the title bar of the back window raises it
+        compositor.handle_mouse(mouse(-140, -105, false));
+        compositor.handle_mouse(mouse(0, 0, true));
+        compositor.handle_mouse(mouse(0, 0, false));
+        assert_eq!(compositor.cursor(), (20, 15));
+        assert_eq!(compositor.focused(), Some(back));
+        compositor.render();
+        let screen = compositor.screen();
+        assert_eq!(screen.pixel(108, 50), Rgb::from_hex(0xc0c0c0));
+        assert_eq!(screen.pixel(150, 50), Rgb::from_hex(0x808080));
+        // the cursor left the middle
+        assert_eq!(screen.pixel(160, 120), Rgb::from_hex(0xc0c0c0));
+
+        compositor.remove_window(back);
+        compositor.render();
+        assert_eq!(compositor.focused(), Some(front));
+        let screen = compositor.screen();
+        assert_eq!(screen.pixel(20, 15), Rgb::from_hex(0x000000));
+        assert_eq!(screen.pixel(30, 20), DESKTOP);
+        assert_eq!(screen.pixel(150, 50), Rgb::from_hex(0x000080));
+    }
+
+    #[test_case]
+    fn test_events_reach_focused_widget() {
+        let (first, first_events) = recorder(Rect::new(0, 0, 40, 20));
+        let (second, second_events) = recorder(Rect::new(50, 0, 40, 20));
+        let mut compositor = Compositor::new(Surface::new(320, 240));
+        let window = Window::new("window", 100, 100, 100, 50).with(first).with(second);
+        let id = compositor.add_window(window);
+        let content = compositor.window(id).unwrap().content();
+
+        compositor.handle_key(key(KeyCode::A, Some('a')));
+        compositor.handle_key(key(KeyCode::Tab, Some('\t')));
+        compositor.handle_key(key(KeyCode::W, Some('w')));
+        assert_eq!(*first_events.lock(), [Event::Key(key(KeyCode::A, Some('a')))]);
+        assert_eq!(*second_events.lock(), [Event::Key(key(KeyCode::W, Some('w')))]);
+
+        // clicking on the first one gives it back the focus
+        let (dx, dy) = (content.x + 5 - 160, content.y + 7 - 120);
+        compositor.handle_mouse(mouse(dx as i16, dy as i16, false));
+        compositor.handle_mouse(mouse(0, 0, true));
+        compositor.handle_mouse(mouse(100, 0, false));
+        compositor.handle_key(key(KeyCode::Q, Some('q')));
+        assert_eq!(first_events.lock()[1..], [
+            Event::Press { x: 5, y: 7 },
+            Event::Release { x: 105, y: 7 },
+            Event::Key(key(KeyCode::Q, Some('q'))),
+        ]);
+
+        // dragging the title bar moves the window
+        compositor.handle_mouse(mouse(-100, -20, true));
+        compositor.handle_mouse(mouse(-50, 30, true));
+        compositor.handle_mouse(mouse(0, 0, false));
+        assert_eq!(compositor.window(id).unwrap().rect().x, 50);
+        assert_eq!(compositor.window(id).unwrap().rect().y, 130);
+        assert_eq!(second_events.lock().len(), 1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Terminal window, showing the console.
+//!
+//! `TerminalConsole` is the `Console` that `vga_buffer::Writer` prints on,
+//! it keeps the cells and the rectangle of them that changed. The
+//! `Terminal` widget draws that rectangle when the compositor asks for its
+//! damage, and prints the keys typed while it is focused on the virtual
+//! terminal shown.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::task::{Context, Poll};
+
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::widget::Widget;
+use super::{Event, FONT, FONT_HEIGHT, FONT_WIDTH};
+use crate::console::Console;
+use crate::graphics::{Canvas, Rect, Target};
+use crate::tprint;
+use crate::task::keyboard::KeyState;
+use crate::vga_buffer::{self, ScreenChar, NORMAL_COLOR};
+
+/// Rows of pixels of the underline cursor, from the bottom of the cell
+const CURSOR_HEIGHT: u32 = 2;
+
+/// Widget showing the cells of a `TerminalConsole`
+pub struct Terminal {
+    rect: Rect,
+    screen: Arc<Screen>,
+}
+
+/// Console drawn by a `Terminal`, to pass to `vga_buffer::set_console`
+pub struct TerminalConsole {
+    screen: Arc<Screen>,
+    columns: usize,
+    rows: usize,
+}
+
+struct Screen {
+    /// Only locked with interrupts disabled.
+    state: Mutex<State>,
+    /// Woken when cells change
+    waker: AtomicWaker,
+}
+
+struct State {
+    columns: usize,
+    cells: Vec<ScreenChar>,
+    cursor: Option<(usize, usize)>,
+    /// Cells changed since the terminal last drew them, in cells
+    damage: Rect,
+}
+
+impl Terminal {
+    /// The terminal at `(x, y)` in its window, of `columns` by `rows` cells,
+    /// and the console printing on it.
+    pub fn new(x: i32, y: i32, columns: usize, rows: usize) -> (Self, TerminalConsole) {
+        let blank = ScreenChar::new(b' ', NORMAL_COLOR);
+        let screen = Arc::new(Screen {
+            state: Mutex::new(State {
+                columns,
+                cells: vec![blank; columns * rows],
+                cursor: None,
+                damage: Rect::new(0, 0, columns as u32, rows as u32),
+            }),
+            waker: AtomicWaker::new(),
+        });
+        let rect = Rect::new(x, y, columns as u32 * FONT_WIDTH, rows as u32 * FONT_HEIGHT);
+        let console = TerminalConsole { screen: screen.clone(), columns, rows };
+        (Self { rect, screen }, console)
+    }
+}
+
+impl Widget for Terminal {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, _focused: bool) {
+        let visible = canvas.clip().intersection(rect);
+        if visible.is_empty() {
+            return;
+        }
+        // the cells the visible part is on
+        let cells = Rect::from_corners(
+            (visible.x - rect.x) / FONT_WIDTH as i32,
+            (visible.y - rect.y) / FONT_HEIGHT as i32,
+            (visible.right() - 1 - rect.x) / FONT_WIDTH as i32,
+            (visible.bottom() - 1 - rect.y) / FONT_HEIGHT as i32,
+        );
+        // copied to draw with interrupts enabled
+        let (copy, cursor) = interrupts::without_interrupts(|| {
+            let state = self.screen.state.lock();
+            let copy: Vec<ScreenChar> = (cells.y..cells.bottom())
+                .flat_map(|row| (cells.x..cells.right()).map(move |column| (row, column)))
+                .map(|(row, column)| state.cells[row as usize * state.columns + column as usize])
+                .collect();
+            (copy, state.cursor)
+        });
+        for (index, c) in copy.into_iter().enumerate() {
+            let row = cells.y + (index / cells.width as usize) as i32;
+            let column = cells.x + (index % cells.width as usize) as i32;
+            let cell = Rect::new(
+                rect.x + column * FONT_WIDTH as i32,
+                rect.y + row * FONT_HEIGHT as i32,
+                FONT_WIDTH,
+                FONT_HEIGHT,
+            );
+            canvas.fill_rect(cell, c.background);
+            canvas.fill_mask(cell, c.text, |x, y| FONT.pixel(c.ascii_character, x, y));
+            if cursor == Some((row as usize, column as usize)) {
+                let y = cell.bottom() - CURSOR_HEIGHT as i32;
+                canvas.fill_rect(Rect::new(cell.x, y, FONT_WIDTH, CURSOR_HEIGHT), c.text);
+            }
+        }
+    }
+
+    fn handle(&mut self, event: &Event) -> bool {
+        if let Event::Key(key) = event {
+            if let (KeyState::Down, Some(c)) = (key.state, key.unicode) {
+                // drawn again once printed
+                tprint!(vga_buffer::active_terminal(), "{c}");
+            }
+        }
+        false
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn poll_damage(&mut self, cx: &mut Context<'_>) -> Poll<Rect> {
+        self.screen.waker.register(cx.waker());
+        let damage = interrupts::without_interrupts(|| {
+            core::mem::take(&mut self.screen.state.lock().damage)
+        });
+        if damage.is_empty() {
+            return Poll::Pending;
+        }
+        Poll::Ready(Rect::new(
+            damage.x * FONT_WIDTH as i32,
+            damage.y * FONT_HEIGHT as i32,
+            damage.width * FONT_WIDTH,
+            damage.height * FONT_HEIGHT,
+        ))
+    }
+}
+
+impl Screen {
+    /// Calls `f` with the state locked, then wakes the terminal if it
+    /// marked cells as damaged.
+    fn update<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        let (result, damaged) = interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+            let result = f(&mut state);
+            (result, !state.damage.is_empty())
+        });
+        if damaged {
+            self.waker.wake();
+        }
+        result
+    }
+}
+
+impl State {
+    fn damage(&mut self, row: usize, column: usize) {
+        self.damage = self.damage.union(Rect::new(column as i32, row as i32, 1, 1));
+    }
+}
+
+impl Console for TerminalConsole {
+    fn width(&self) -> usize {
+        self.columns
+    }
+
+    fn height(&self) -> usize {
+        self.rows
+    }
+
+    fn read(&self, row: usize, column: usize) -> ScreenChar {
+        interrupts::without_interrupts(|| {
+            self.screen.state.lock().cells[row * self.columns + column]
+        })
+    }
+
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar) {
+        self.screen.update(|state| {
+            let cell = &mut state.cells[row * state.columns + column];
+            if *cell != c {
+                *cell = c;
+                state.damage(row, column);
+            }
+        });
+    }
+
+    fn set_cursor(&mut self, position: Option<(usize, usize)>) {
+        self.screen.update(|state| {
+            let previous = core::mem::replace(&mut state.cursor, position);
+            if previous != position {
+                for (row, column) in previous.into_iter().chain(position) {
+                    state.damage(row, column);
+                }
+            }
+        });
+    }
+
+    fn scroll(&mut self) {
+        let (columns, rows) = (self.columns, self.rows);
+        self.screen.update(|state| {
+            state.cells.copy_within(columns.., 0);
+            let moved = Rect::new(0, 0, columns as u32, rows as u32 - 1);
+            state.damage = state.damage.union(moved);
+        });
+    }
+}
@@ -0,0 +1,232 @@
+//! Widgets drawn in the content of windows.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::task::{Context, Poll};
+
+use super::{draw_text, text_width, Event, FONT_HEIGHT};
+use crate::graphics::{Canvas, Rect, Rgb, Target};
+use crate::task::keyboard::KeyState;
+
+const TEXT: Rgb = Rgb::from_hex(0x000000);
+const FACE: Rgb = Rgb::from_hex(0xc0c0c0);
+const LIGHT: Rgb = Rgb::from_hex(0xffffff);
+const SHADOW: Rgb = Rgb::from_hex(0x808080);
+const FIELD: Rgb = Rgb::from_hex(0xffffff);
+const FOCUS: Rgb = Rgb::from_hex(0x000080);
+
+/// Pixels between the border of a widget and its text
+const PADDING: i32 = 3;
+
+/// Part of the content of a window
+pub trait Widget: Send {
+    /// Where the widget is, relative to the content of its window
+    fn rect(&self) -> Rect;
+
+    /// Draws the widget at `rect`, its place on the screen.
+    fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, focused: bool);
+
+    /// Handles `event`, returns whether the widget must be drawn again.
+    fn handle(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    /// Whether the widget takes the keyboard events when clicked on
+    fn focusable(&self) -> bool {
+        false
+    }
+
+    /// Returns the part of the widget that changed by itself, relative to
+    /// it, or registers the waker of `cx` to be woken when some does.
+    fn poll_damage(&mut self, _cx: &mut Context<'_>) -> Poll<Rect> {
+        Poll::Pending
+    }
+}
+
+/// A line of text
+pub struct Label {
+    rect: Rect,
+    text: String,
+}
+
+/// A button calling a function when clicked on
+pub struct Button {
+    rect: Rect,
+    label: String,
+    /// Set while the mouse button is held down on it
+    pressed: bool,
+    on_click: Box<dyn FnMut() + Send>,
+}
+
+/// A line of text to edit
+pub struct TextBox {
+    rect: Rect,
+    text: String,
+}
+
+impl Label {
+    /// The label at `(x, y)` in the window, as large as `text`
+    pub fn new(x: i32, y: i32, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let rect = Rect::new(x, y, text_width(&text), FONT_HEIGHT);
+        Self { rect, text }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Widget for Label {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, _focused: bool) {
+        canvas.fill_rect(rect, FACE);
+        draw_text(canvas, rect.x, rect.y, &self.text, TEXT);
+    }
+}
+
+impl Button {
+    /// The button at `rect` in the window, calling `on_click` when clicked
+    /// on
+    pub fn new(rect: Rect, label: impl Into<String>, on_click: impl FnMut() + Send + 'static) -> Self {
+        Self {
+            rect,
+            label: label.into(),
+            pressed: false,
+            on_click: Box::new(on_click),
+        }
+    }
+}
+
+impl Widget for Button {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, _focused: bool) {
+        let (top_left, bottom_right) = match self.pressed {
+            true => (SHADOW, LIGHT),
+            false => (LIGHT, SHADOW),
+        };
+        canvas.fill_rect(rect, FACE);
+        let (right, bottom) = (rect.right() - 1, rect.bottom() - 1);
+        canvas.draw_line(rect.x, rect.y, right, rect.y, top_left);
+        canvas.draw_line(rect.x, rect.y, rect.x, bottom, top_left);
+        canvas.draw_line(rect.x, bottom, right, bottom, bottom_right);
+        canvas.draw_line(right, rect.y, right, bottom, bottom_right);
+        // centered, and moved down a pixel while pressed
+        let x = rect.x + (rect.width as i32 - text_width(&self.label) as i32) / 2;
+        let y = rect.y + (rect.height as i32 - FONT_HEIGHT as i32) / 2 + self.pressed as i32;
+        canvas.with_clip(rect, |canvas| draw_text(canvas, x, y, &self.label, TEXT));
+    }
+
+    fn handle(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::Press { .. } => {
+                self.pressed = true;
+                true
+            }
+            Event::Release { x, y } if self.pressed => {
+                self.pressed = false;
+                if Rect::new(0, 0, self.rect.width, self.rect.height).contains(x, y) {
+                    (self.on_click)();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TextBox {
+    /// An empty text box at `rect` in the window
+    pub fn new(rect: Rect) -> Self {
+        Self { rect, text: String::new() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Widget for TextBox {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, rect: Rect, focused: bool) {
+        canvas.fill_rect(rect, FIELD);
+        canvas.draw_rect(rect, if focused { FOCUS } else { SHADOW });
+        let inside = Rect::new(
+            rect.x + PADDING,
+            rect.y,
+            rect.width.saturating_sub(2 * PADDING as u32),
+            rect.height,
+        );
+        let y = rect.y + (rect.height as i32 - FONT_HEIGHT as i32) / 2;
+        // the end of the text stays visible
+        let width = text_width(&self.text) as i32;
+        let x = inside.x + (inside.width as i32 - width - 1).min(0);
+        canvas.with_clip(inside, |canvas| {
+            draw_text(canvas, x, y, &self.text, TEXT);
+            if focused {
+                canvas.fill_rect(Rect::new(x + width, y, 1, FONT_HEIGHT), TEXT);
+            }
+        });
+    }
+
+    fn handle(&mut self, event: &Event) -> bool {
+        let Event::Key(key) = event else {
+            return false;
+        };
+        match key.unicode {
+            _ if key.state != KeyState::Down => false,
+            Some('\x08') => self.text.pop().is_some(),
+            Some(c) if !c.is_control() => {
+                self.text.push(c);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[test_case]
+fn test_text_box_and_button() {
+    use crate::task::keyboard::{KeyCode, KeyEvent, Modifiers};
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    let key = |code, unicode| Event::Key(KeyEvent {
+        code,
+        state: KeyState::Down,
+        modifiers: Modifiers::default(),
+        unicode,
+    });
+    let mut text_box = TextBox::new(Rect::new(0, 0, 100, 20));
+    assert!(text_box.handle(&key(KeyCode::H, Some('h'))));
+    assert!(text_box.handle(&key(KeyCode::I, Some('i'))));
+    assert!(!text_box.handle(&key(KeyCode::Return, Some('\n'))));
+    assert!(!text_box.handle(&key(KeyCode::F1, None)));
+    assert!(text_box.handle(&key(KeyCode::Backspace, Some('\x08'))));
+    assert_eq!(text_box.text(), "h");
+
+    let clicks = Arc::new(AtomicUsize::new(0));
+    let counter = clicks.clone();
+    let mut button = Button::new(Rect::new(10, 10, 60, 20), "OK", move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    button.handle(&Event::Press { x: 5, y: 5 });
+    button.handle(&Event::Release { x: 59, y: 19 });
+    // released outside of the button
+    button.handle(&Event::Press { x: 5, y: 5 });
+    button.handle(&Event::Release { x: 60, y: 5 });
+    assert_eq!(clicks.load(Ordering::Relaxed), 1);
+}
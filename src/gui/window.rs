@@ -0,0 +1,157 @@
+//! Windows: a title bar and widgets in a frame.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::widget::Widget;
+use super::{draw_text, FONT_HEIGHT};
+use crate::graphics::{Canvas, Rect, Rgb, Target};
+
+/// Width of the border around the windows
+const BORDER: u32 = 1;
+/// Height of the title bars
+pub(super) const TITLE_HEIGHT: u32 = FONT_HEIGHT + 4;
+
+const BORDER_COLOR: Rgb = Rgb::from_hex(0x000000);
+const BACKGROUND: Rgb = Rgb::from_hex(0xc0c0c0);
+const TITLE_FOCUSED: Rgb = Rgb::from_hex(0x000080);
+const TITLE_UNFOCUSED: Rgb = Rgb::from_hex(0x808080);
+const TITLE_TEXT: Rgb = Rgb::from_hex(0xffffff);
+
+/// A window, to add to the `Compositor`
+pub struct Window {
+    title: String,
+    /// The whole window on the screen, border included
+    rect: Rect,
+    widgets: Vec<Box<dyn Widget>>,
+    /// Index of the widget taking the keyboard events
+    focus: Option<usize>,
+}
+
+impl Window {
+    /// The window at `(x, y)` with content of `width` by `height` pixels
+    pub fn new(title: impl Into<String>, x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            title: title.into(),
+            rect: Rect::new(x, y, width + 2 * BORDER, height + TITLE_HEIGHT + 2 * BORDER),
+            widgets: Vec::new(),
+            focus: None,
+        }
+    }
+
+    /// Adds `widget` to the content, the first focusable one taking the
+    /// keyboard events.
+    pub fn with(mut self, widget: impl Widget + 'static) -> Self {
+        if self.focus.is_none() && widget.focusable() {
+            self.focus = Some(self.widgets.len());
+        }
+        self.widgets.push(Box::new(widget));
+        self
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The whole window on the screen
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// The part of the window the widgets are in
+    pub fn content(&self) -> Rect {
+        Rect::new(
+            self.rect.x + BORDER as i32,
+            self.rect.y + (BORDER + TITLE_HEIGHT) as i32,
+            self.rect.width - 2 * BORDER,
+            self.rect.height - TITLE_HEIGHT - 2 * BORDER,
+        )
+    }
+
+    /// The part of the window to drag it by
+    pub(super) fn title_bar(&self) -> Rect {
+        Rect::new(
+            self.rect.x + BORDER as i32,
+            self.rect.y + BORDER as i32,
+            self.rect.width - 2 * BORDER,
+            TITLE_HEIGHT,
+        )
+    }
+
+    pub(super) fn move_to(&mut self, x: i32, y: i32) {
+        self.rect.x = x;
+        self.rect.y = y;
+    }
+
+    pub(super) fn widget_count(&self) -> usize {
+        self.widgets.len()
+    }
+
+    pub(super) fn widget_mut(&mut self, index: usize) -> &mut dyn Widget {
+        &mut *self.widgets[index]
+    }
+
+    /// Where widget `index` is on the screen
+    pub(super) fn widget_rect(&self, index: usize) -> Rect {
+        let content = self.content();
+        let rect = self.widgets[index].rect();
+        Rect { x: rect.x + content.x, y: rect.y + content.y, ..rect }
+    }
+
+    /// Returns the index of the topmost widget at `(x, y)` on the screen.
+    pub(super) fn widget_at(&self, x: i32, y: i32) -> Option<usize> {
+        if !self.content().contains(x, y) {
+            return None;
+        }
+        (0..self.widgets.len()).rev().find(|&index| self.widget_rect(index).contains(x, y))
+    }
+
+    pub(super) fn focus(&self) -> Option<usize> {
+        self.focus
+    }
+
+    /// Gives the keyboard events to widget `index`, if it takes them.
+    /// Returns the widgets to draw again.
+    pub(super) fn set_focus(&mut self, index: usize) -> [Option<usize>; 2] {
+        if !self.widgets[index].focusable() || self.focus == Some(index) {
+            return [None, None];
+        }
+        [self.focus.replace(index), Some(index)]
+    }
+
+    /// Gives the keyboard events to the next focusable widget. Returns the
+    /// widgets to draw again.
+    pub(super) fn focus_next(&mut self) -> [Option<usize>; 2] {
+        let count = self.widgets.len();
+        let start = self.focus.map_or(0, |focus| focus + 1);
+        let next = (start..start + count)
+            .map(|index| index % count)
+            .find(|&index| self.widgets[index].focusable());
+        match next {
+            Some(index) => self.set_focus(index),
+            None => [None, None],
+        }
+    }
+
+    /// Draws the window, as the focused one or not.
+    pub(super) fn draw(&self, canvas: &mut Canvas<'_, dyn Target + '_>, focused: bool) {
+        canvas.draw_rect(self.rect, BORDER_COLOR);
+        let title_bar = self.title_bar();
+        let title_color = if focused { TITLE_FOCUSED } else { TITLE_UNFOCUSED };
+        canvas.fill_rect(title_bar, title_color);
+        canvas.with_clip(title_bar, |canvas| {
+            draw_text(canvas, title_bar.x + 4, title_bar.y + 2, &self.title, TITLE_TEXT);
+        });
+        let content = self.content();
+        canvas.fill_rect(content, BACKGROUND);
+        canvas.with_clip(content, |canvas| {
+            for (index, widget) in self.widgets.iter().enumerate() {
+                let rect = self.widget_rect(index);
+                if !rect.intersection(canvas.clip()).is_empty() {
+                    widget.draw(canvas, rect, focused && self.focus == Some(index));
+                }
+            }
+        });
+    }
+}
@@ -1,13 +1,27 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use crate::hlt_loop;
 
 use pic8259::ChainedPics;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
-use crate::{eprintln, gdt};
+use crate::{apic, eprintln, gdt, smp, thread};
 use lazy_static::lazy_static;
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+/// Software interrupt used by threads to yield, see `thread::yield_now`
+pub const YIELD_VECTOR: u8 = 0x81;
+
+/// Frequency of the timer interrupt, in Hz
+pub const TIMER_FREQUENCY: u32 = 100;
+
+/// Frequency of the oscillator driving the PIT, in Hz
+const PIT_FREQUENCY: u32 = 1_193_182;
+
+/// Number of timer interrupts since boot
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
@@ -16,6 +30,11 @@ pub static PICS: spin::Mutex<ChainedPics> =
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    /// COM2 and COM4
+    Serial2 = PIC_1_OFFSET + 3,
+    /// COM1 and COM3
+    Serial1,
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 lazy_static! {
@@ -29,11 +48,29 @@ lazy_static! {
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
         }
 
-        idt[InterruptIndex::Timer.into()]
-            .set_handler_fn(timer_interrupt_handler);
+        // these can switch threads, so they go through `thread::context`
+        unsafe {
+            idt[InterruptIndex::Timer.into()]
+                .set_handler_addr(thread::context::timer_entry());
+            idt[YIELD_VECTOR.into()]
+                .set_handler_addr(thread::context::yield_entry());
+            idt[apic::TIMER_VECTOR.into()]
+                .set_handler_addr(thread::context::apic_timer_entry());
+        }
         
         idt[InterruptIndex::Keyboard.into()]
             .set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Mouse.into()]
+            .set_handler_fn(mouse_interrupt_handler);
+        idt[InterruptIndex::Serial1.into()]
+            .set_handler_fn(serial1_interrupt_handler);
+        idt[InterruptIndex::Serial2.into()]
+            .set_handler_fn(serial2_interrupt_handler);
+
+        idt[smp::CALL_VECTOR.into()]
+            .set_handler_fn(smp::call::call_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR.into()]
+            .set_handler_fn(spurious_interrupt_handler);
 
         idt.page_fault.set_handler_fn(page_fault_handler);
 
@@ -53,14 +90,31 @@ extern "x86-interrupt" fn double_fault_handler(
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(
-    _stack_frame: InterruptStackFrame)
-{
-    // TODO: add timer interrupt handler I guess
+/// Called by `thread::context::timer_entry` with the stack pointer of the
+/// interrupted thread, returns the stack pointer of the thread to resume.
+pub(crate) extern "C" fn timer_interrupt_handler(stack_pointer: u64) -> u64 {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::ps2::tick();
+
+    // the next thread will not come back here, so notify first
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.into());
     }
+    thread::scheduler::preempt(stack_pointer, now)
+}
+
+/// Timer interrupt of the application processors, that only schedules
+/// threads: `ticks` is counted by the bootstrap processor.
+pub(crate) extern "C" fn apic_timer_interrupt_handler(stack_pointer: u64) -> u64 {
+    apic::end_of_interrupt();
+    thread::scheduler::preempt(stack_pointer, ticks())
+}
+
+/// Returns the number of timer interrupts since boot,
+/// see `TIMER_FREQUENCY`.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(
@@ -71,7 +125,9 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
 
-    crate::task::keyboard::add_scancode(scancode);
+    if !crate::ps2::take_response(scancode) {
+        crate::task::keyboard::add_scancode(scancode);
+    }
 
     unsafe {
         PICS.lock()
@@ -79,6 +135,50 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     }
 }
 
+extern "x86-interrupt" fn mouse_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let byte: u8 = unsafe { port.read() };
+
+    crate::task::mouse::add_byte(byte);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Mouse.into());
+    }
+}
+
+extern "x86-interrupt" fn serial1_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    crate::serial::handle_interrupt(4);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Serial1.into());
+    }
+}
+
+extern "x86-interrupt" fn serial2_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    crate::serial::handle_interrupt(3);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Serial2.into());
+    }
+}
+
+/// Spurious interrupts of the local APIC, they are not acknowledged.
+extern "x86-interrupt" fn spurious_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+}
+
 extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
@@ -98,6 +198,41 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Sets the PIT to fire the timer interrupt at `TIMER_FREQUENCY`.
+pub fn init_timer() {
+    use x86_64::instructions::port::Port;
+
+    let mut command: Port<u8> = Port::new(0x43);
+    let mut channel_0: Port<u8> = Port::new(0x40);
+    let divisor = (PIT_FREQUENCY / TIMER_FREQUENCY) as u16;
+    unsafe {
+        // channel 0, low then high byte, square wave generator
+        command.write(0x36);
+        channel_0.write(divisor as u8);
+        channel_0.write((divisor >> 8) as u8);
+    }
+}
+
+/// Unmasks `irq` on the PICs, the ones the BIOS left masked stay so until
+/// their driver calls this.
+pub fn unmask_irq(irq: u8) {
+    use x86_64::instructions::port::Port;
+
+    let (port, bit) = match irq {
+        0..=7 => (0x21, irq),
+        _ => (0xa1, irq - 8),
+    };
+    let mut data: Port<u8> = Port::new(port);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // locked so that it does not race with `initialize`
+        let _pics = PICS.lock();
+        unsafe {
+            let masks = data.read();
+            data.write(masks & !(1 << bit));
+        }
+    });
+}
+
 impl From<InterruptIndex> for u8 {
     fn from(value: InterruptIndex) -> Self {
         value as u8
@@ -9,12 +9,22 @@
 #![reexport_test_harness_main = "test_main"]
 
 pub mod vga_buffer;
+pub mod console;
+pub mod framebuffer;
+pub mod graphics;
+pub mod gui;
 pub mod serial;
 pub mod interrupts;
 pub mod gdt;
 pub mod memory;
 pub mod allocator;
 pub mod task;
+pub mod thread;
+pub mod acpi;
+pub mod apic;
+pub mod smp;
+pub mod ps2;
+pub mod pci;
 pub mod qemu;
 
 use crate::qemu::*;
@@ -24,8 +34,14 @@ extern crate alloc;
 
 pub fn init() {
     gdt::init();
+    smp::init();
     interrupts::init_idt();
+    interrupts::init_timer();
     unsafe { interrupts::PICS.lock().initialize() };
+    if let Err(err) = ps2::init() {
+        eprintln!("PS/2 controller initialization failed: {:?}", err);
+    }
+    serial::init();
     x86_64::instructions::interrupts::enable();
 }
 
@@ -83,8 +99,24 @@ use bootloader::{entry_point, BootInfo};
 entry_point!(test_kernel_main);
 
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    use memory::BootInfoFrameAllocator;
+    use x86_64::VirtAddr;
+
     init();
+
+    // the executor tests need a heap
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    vga_buffer::init_scrollback();
+    vga_buffer::init_terminals();
+    memory::init_kernel_memory(mapper, frame_allocator);
+
     test_main();
 
     hlt_loop();
@@ -9,10 +9,15 @@ extern crate alloc;
 use core::panic::PanicInfo;
 use bootloader::{BootInfo, entry_point};
 use swag_kernel::allocator;
+use swag_kernel::gui;
 use swag_kernel::println;
-use swag_kernel::task::executor::Executor;
+use swag_kernel::smp;
+use swag_kernel::task::{self, executor::Executor};
 use swag_kernel::task::keyboard;
+use swag_kernel::task::serial;
 use swag_kernel::task::Task;
+use swag_kernel::thread;
+use swag_kernel::vga_buffer;
 
 #[cfg(not(test))]
 use swag_kernel::hlt_loop;
@@ -39,12 +44,40 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     // initialize heap
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    vga_buffer::init_scrollback();
+    vga_buffer::init_terminals();
+    memory::init_kernel_memory(mapper, frame_allocator);
+    let compositor = gui::init()
+        .inspect_err(|err| println!("Staying in text mode: {:?}", err))
+        .ok();
+
+    // the executor keeps running on the boot thread
+    thread::init();
+
+    // the other CPUs share the tasks of the BSP's executor
+    let mut executor = Executor::default();
+    task::set_spawner(executor.spawner());
+    let cpus = smp::start_aps(ap_main);
+    println!("{} CPUs running", cpus);
 
     #[cfg(test)]
     test_main();
 
-    let mut executor = Executor::default();
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    executor.spawn(Task::new(keyboard::run()).with_name("keyboard"));
+    // the terminal window prints the keys itself
+    match compositor {
+        Some(compositor) => executor.spawn(Task::new(compositor.run()).with_name("compositor")),
+        None => executor.spawn(Task::new(keyboard::print_keypresses()).with_name("keypress printer")),
+    }
+    // echoes what is typed with `-serial stdio`, until there is a shell
+    executor.spawn(Task::new(serial::echo(swag_kernel::serial::SERIAL1)).with_name("serial echo"));
+    executor.run();
+}
+
+/// Runs on each application processor, once it is started.
+fn ap_main() -> ! {
+    let spawner = task::spawner().expect("executor not created");
+    let mut executor = Executor::join(spawner);
     executor.run();
 }
 
@@ -53,6 +86,10 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     use swag_kernel::eprintln;
+    // the compositor drawing the terminal window may not run anymore
+    gui::take_over_console();
+    // printed on the first terminal, show it even if another one was
+    vga_buffer::switch_terminal(0);
     eprintln!("{}", info);
 
     hlt_loop();
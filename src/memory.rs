@@ -1,4 +1,11 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::Mutex;
+use x86_64::instructions::{interrupts, tlb};
+use x86_64::structures::paging::mapper::{MapToError, UnmapError};
+use x86_64::structures::paging::page::PageRangeInclusive;
+use x86_64::structures::paging::{Mapper, Page, PageSize, PageTableFlags};
 use x86_64::{VirtAddr, structures::paging::{PageTable, OffsetPageTable, FrameAllocator, Size4KiB, PhysFrame}, PhysAddr};
 
 
@@ -10,6 +17,7 @@ use x86_64::{VirtAddr, structures::paging::{PageTable, OffsetPageTable, FrameAll
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoir aliasing `&mut` references (which is undefined behaviour).
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
@@ -80,3 +88,138 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
         frame
     }
 }
+
+
+/// Page table and frame allocator used for the mappings made after boot
+///
+/// Only locked with interrupts disabled.
+static KERNEL_MEMORY: Mutex<Option<KernelMemory>> = Mutex::new(None);
+
+/// Offset at which the bootloader mapped the physical memory, see `init`
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Start of the virtual memory region used by `map_mmio`
+const MMIO_START: u64 = 0x_5555_0000_0000;
+
+struct KernelMemory {
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+}
+
+/// Hands the page table and frame allocator over to this module, so that
+/// drivers can map memory after boot (see `map_mmio` and `identity_map`).
+pub fn init_kernel_memory(
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+) {
+    interrupts::without_interrupts(|| {
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        assert!(kernel_memory.is_none(), "kernel memory already initialized");
+        *kernel_memory = Some(KernelMemory { mapper, frame_allocator });
+    });
+}
+
+/// Runs `f` with the kernel page table and frame allocator.
+///
+/// Panics if `init_kernel_memory` was not called.
+fn with_kernel_memory<R>(
+    f: impl FnOnce(&mut OffsetPageTable<'static>, &mut BootInfoFrameAllocator) -> R,
+) -> R {
+    interrupts::without_interrupts(|| {
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        let kernel_memory = kernel_memory.as_mut()
+            .expect("kernel memory not initialized");
+        f(&mut kernel_memory.mapper, &mut kernel_memory.frame_allocator)
+    })
+}
+
+/// Returns the virtual address at which the physical address `addr` is
+/// mapped by the bootloader.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
+/// Maps `size` bytes of memory mapped IO starting at `addr` as uncacheable,
+/// and returns the virtual address of `addr`. Nothing is mapped if `size`
+/// is 0.
+pub fn map_mmio(addr: PhysAddr, size: u64) -> VirtAddr {
+    static NEXT_PAGE: AtomicU64 = AtomicU64::new(MMIO_START);
+
+    if size == 0 {
+        // not mapped, but there is no byte to access there either
+        return VirtAddr::new(NEXT_PAGE.load(Ordering::Relaxed));
+    }
+    let frames = PhysFrame::<Size4KiB>::range_inclusive(
+        PhysFrame::containing_address(addr),
+        PhysFrame::containing_address(addr + (size - 1)),
+    );
+    let pages_size = frames.count() as u64 * Size4KiB::SIZE;
+    let start = VirtAddr::new(NEXT_PAGE.fetch_add(pages_size, Ordering::Relaxed));
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH;
+
+    with_kernel_memory(|mapper, frame_allocator| {
+        for (i, frame) in frames.enumerate() {
+            let page = Page::containing_address(start + i as u64 * Size4KiB::SIZE);
+            unsafe {
+                mapper.map_to(page, frame, flags, frame_allocator)
+                    .expect("failed to map MMIO")
+                    .flush();
+            }
+        }
+    });
+    start + addr.as_u64() % Size4KiB::SIZE
+}
+
+/// Unmaps `size` bytes mapped by `map_mmio` at `addr`.
+///
+/// The virtual memory is not reused.
+pub fn unmap_mmio(addr: VirtAddr, size: u64) {
+    if size == 0 {
+        return;
+    }
+    let pages = Page::range_inclusive(
+        Page::containing_address(addr),
+        Page::containing_address(addr + (size - 1)),
+    );
+    with_kernel_memory(|mapper, _| {
+        for page in pages {
+            let (_, flush) = mapper.unmap(page).expect("failed to unmap MMIO");
+            flush.flush();
+        }
+    });
+    shootdown(pages);
+}
+
+/// Unmaps `page` on all the CPUs, and returns the frame it was mapped to.
+pub fn unmap(page: Page) -> Result<PhysFrame, UnmapError> {
+    let (frame, flush) = with_kernel_memory(|mapper, _| mapper.unmap(page))?;
+    flush.flush();
+    shootdown(Page::range_inclusive(page, page));
+    Ok(frame)
+}
+
+/// Flushes `pages` from the TLB of the other CPUs, that may still use the
+/// old mappings. Must not be called with a lock held, see `smp::call`.
+fn shootdown(pages: PageRangeInclusive) {
+    crate::smp::call_on_others(move || {
+        for page in pages {
+            tlb::flush(page.start_address());
+        }
+    });
+}
+
+/// Maps `frame` at the same virtual address, e.g. for code running before
+/// paging is enabled. Does nothing if the page is mapped to it already.
+pub fn identity_map(frame: PhysFrame) {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    with_kernel_memory(|mapper, frame_allocator| {
+        match unsafe { mapper.identity_map(frame, flags, frame_allocator) } {
+            Ok(flush) => flush.flush(),
+            Err(MapToError::PageAlreadyMapped(mapped)) if mapped == frame => {},
+            Err(err) => panic!("failed to identity map {:?}: {:?}", frame, err),
+        }
+    });
+}
@@ -0,0 +1,110 @@
+//! PCI configuration space, through the legacy 0xcf8 and 0xcfc ports.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+
+/// Configuration space registers
+const VENDOR_ID: u8 = 0x00;
+const HEADER_TYPE: u8 = 0x0e;
+const BAR0: u8 = 0x10;
+
+/// Header type bit of the devices with more than one function
+const MULTIFUNCTION: u8 = 1 << 7;
+
+/// Vendor ID read when there is no device
+const NO_DEVICE: u16 = 0xffff;
+
+/// Address and data ports, used in pairs
+///
+/// Only locked with interrupts disabled.
+static PORTS: Mutex<(Port<u32>, Port<u32>)> =
+    Mutex::new((Port::new(CONFIG_ADDRESS), Port::new(CONFIG_DATA)));
+
+/// Location of a PCI function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Location {
+    /// Reads the aligned 32 bits of the configuration space at `offset`.
+    pub fn read_config(self, offset: u8) -> u32 {
+        let address = 1 << 31
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset & 0xfc) as u32;
+        interrupts::without_interrupts(|| {
+            let (address_port, data_port) = &mut *PORTS.lock();
+            unsafe {
+                address_port.write(address);
+                data_port.read()
+            }
+        })
+    }
+
+    pub fn vendor_id(self) -> u16 {
+        self.read_config(VENDOR_ID) as u16
+    }
+
+    pub fn device_id(self) -> u16 {
+        (self.read_config(VENDOR_ID) >> 16) as u16
+    }
+
+    fn header_type(self) -> u8 {
+        (self.read_config(HEADER_TYPE) >> 16) as u8
+    }
+
+    /// Address of the memory base address register `index`, `None` for IO
+    /// space registers.
+    pub fn memory_bar(self, index: u8) -> Option<PhysAddr> {
+        let offset = BAR0 + index * 4;
+        let bar = self.read_config(offset);
+        if bar & 1 != 0 {
+            return None;
+        }
+        let mut address = (bar & !0xf) as u64;
+        // 64 bit registers take the next one too
+        if (bar >> 1) & 0b11 == 0b10 {
+            address |= (self.read_config(offset + 4) as u64) << 32;
+        }
+        Some(PhysAddr::new(address))
+    }
+}
+
+/// Returns every function present, on every bus.
+pub fn functions() -> impl Iterator<Item = Location> {
+    (0..=255u8)
+        .flat_map(|bus| (0..32u8).map(move |device| Location { bus, device, function: 0 }))
+        .filter(|location| location.vendor_id() != NO_DEVICE)
+        .flat_map(|location| {
+            let functions = match location.header_type() & MULTIFUNCTION {
+                0 => 1,
+                _ => 8,
+            };
+            (0..functions).map(move |function| Location { function, ..location })
+        })
+        .filter(|location| location.vendor_id() != NO_DEVICE)
+}
+
+/// Returns the first function with these IDs.
+pub fn find(vendor_id: u16, device_id: u16) -> Option<Location> {
+    functions().find(|location| {
+        location.vendor_id() == vendor_id && location.device_id() == device_id
+    })
+}
+
+#[test_case]
+fn test_host_bridge_found() {
+    // QEMU's i440FX host bridge
+    let host_bridge = Location { bus: 0, device: 0, function: 0 };
+    assert_eq!(functions().next(), Some(host_bridge));
+    assert_eq!(find(0x8086, 0x1237), Some(host_bridge));
+}
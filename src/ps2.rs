@@ -0,0 +1,493 @@
+//! 8042 PS/2 controller.
+//!
+//! `init` tests the controller, detects the devices on both of its channels
+//! and resets them. The keyboard is left in scancode set 2, that the
+//! controller translates to the set 1 decoded by `task::keyboard`, and the
+//! mouse with its scroll wheel and extra buttons unlocked for `task::mouse`.
+//!
+//! During `init` the controller is polled with its interrupts disabled.
+//! Afterwards, the answers of the keyboard to commands like `set_leds` come
+//! through the keyboard interrupt handler, which hands them over to
+//! `take_response` instead of queuing them as scancodes, and wakes the task
+//! waiting for them.
+
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicU16, Ordering};
+use core::task::Poll;
+
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+/// Status register bits
+const OUTPUT_FULL: u8 = 1 << 0;
+const INPUT_FULL: u8 = 1 << 1;
+
+/// Controller commands
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND: u8 = 0xa7;
+const ENABLE_SECOND: u8 = 0xa8;
+const TEST_SECOND: u8 = 0xa9;
+const TEST_CONTROLLER: u8 = 0xaa;
+const TEST_FIRST: u8 = 0xab;
+const DISABLE_FIRST: u8 = 0xad;
+const ENABLE_FIRST: u8 = 0xae;
+const WRITE_SECOND: u8 = 0xd4;
+
+/// Configuration byte bits
+const FIRST_INTERRUPT: u8 = 1 << 0;
+const SECOND_INTERRUPT: u8 = 1 << 1;
+const SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+const TRANSLATION: u8 = 1 << 6;
+
+/// Device commands
+const SET_LEDS: u8 = 0xed;
+const SCANCODE_SET: u8 = 0xf0;
+const IDENTIFY: u8 = 0xf2;
+const SET_TYPEMATIC: u8 = 0xf3;
+const SET_SAMPLE_RATE: u8 = 0xf3;
+const ENABLE_SCANNING: u8 = 0xf4;
+const DISABLE_SCANNING: u8 = 0xf5;
+const RESET: u8 = 0xff;
+
+/// Responses
+const CONTROLLER_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+const DEVICE_TEST_PASSED: u8 = 0xaa;
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+
+/// How many times the status is polled before giving up, about 100 ms
+const POLL_LIMIT: usize = 100_000;
+
+/// Reports per second of the mouse
+const SAMPLE_RATE: u8 = 100;
+
+/// How long the keyboard interrupt handler is waited for, in timer ticks
+const RESPONSE_TICKS: u64 = 10;
+
+/// How many times a byte is sent again when the device asks for it
+const RESEND_LIMIT: usize = 3;
+
+/// Values of `RESPONSE` that are not a response byte
+const NO_COMMAND: u16 = 0x100;
+const AWAITING_RESPONSE: u16 = 0x200;
+
+/// Only locked with interrupts disabled.
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller::new());
+
+/// Device found on each channel by `init`
+static DEVICES: Mutex<[Option<DeviceType>; 2]> = Mutex::new([None; 2]);
+
+/// Response of the keyboard to the byte sent by `keyboard_command`
+static RESPONSE: AtomicU16 = AtomicU16::new(NO_COMMAND);
+
+/// Woken when `RESPONSE` is set, and on each timer tick while it is awaited
+static RESPONSE_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Held while a `keyboard_command` is in flight
+static KEYBOARD: crate::task::sync::Mutex<()> = crate::task::sync::Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The controller or a device did not answer in time
+    Timeout,
+    ControllerTestFailed(u8),
+    PortTestFailed(Channel, u8),
+    DeviceTestFailed(Channel, u8),
+    /// A device kept asking to resend this byte
+    NotAcknowledged(u8),
+    /// There is no keyboard on the first channel
+    NoKeyboard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// Usually the keyboard, interrupt 1
+    First,
+    /// Usually the mouse, interrupt 12
+    Second,
+}
+
+/// Device type, from its answer to the identify command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// Answers nothing
+    AtKeyboard,
+    Mf2Keyboard,
+    Mouse,
+    ScrollMouse,
+    FiveButtonMouse,
+    Unknown,
+}
+
+/// Keyboard LEDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+/// Key repeat of the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Typematic {
+    /// Delay before the first repeat, `(delay + 1) * 250` ms from 0 to 3
+    pub delay: u8,
+    /// From 0 (30 repeats per second) to 31 (2 per second)
+    pub rate: u8,
+}
+
+struct Controller {
+    data: Port<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+}
+
+/// Initializes the controller and its devices, and enables their
+/// interrupts, unmasking the IRQ of a mouse. Returns the error of the
+/// first channel, the second one is optional.
+pub fn init() -> Result<(), Error> {
+    let mouse = interrupts::without_interrupts(|| {
+        let mut controller = CONTROLLER.lock();
+        let mut devices = DEVICES.lock();
+        *devices = [None; 2];
+
+        controller.command(DISABLE_FIRST)?;
+        controller.command(DISABLE_SECOND)?;
+        controller.flush();
+
+        let mut config = controller.read_config()?;
+        config &= !(FIRST_INTERRUPT | SECOND_INTERRUPT);
+        config |= TRANSLATION;
+        controller.write_config(config)?;
+
+        match controller.command_with_response(TEST_CONTROLLER)? {
+            CONTROLLER_TEST_PASSED => {}
+            result => return Err(Error::ControllerTestFailed(result)),
+        }
+        // the test may reset the controller
+        controller.write_config(config)?;
+
+        // only a dual channel controller enables the second clock
+        controller.command(ENABLE_SECOND)?;
+        let dual_channel = controller.read_config()? & SECOND_CLOCK_DISABLED == 0;
+        controller.command(DISABLE_SECOND)?;
+
+        controller.test_port(Channel::First)?;
+        controller.command(ENABLE_FIRST)?;
+        let keyboard = controller.init_device(Channel::First)?;
+        if !keyboard.is_keyboard() {
+            return Err(Error::NoKeyboard);
+        }
+        // translated to set 1 by the controller
+        controller.device_command(Channel::First, &[SCANCODE_SET, 2])?;
+        controller.device_command(Channel::First, &[SET_TYPEMATIC, Typematic::default().byte()])?;
+        controller.device_command(Channel::First, &[SET_LEDS, Leds::default().byte()])?;
+        controller.device_command(Channel::First, &[ENABLE_SCANNING])?;
+        devices[0] = Some(keyboard);
+        config |= FIRST_INTERRUPT;
+
+        if dual_channel {
+            let second = controller.test_port(Channel::Second)
+                .and_then(|()| controller.command(ENABLE_SECOND))
+                .and_then(|()| controller.init_device(Channel::Second))
+                .and_then(|device| match device {
+                    DeviceType::Mouse => controller.init_mouse(),
+                    device => Ok(device),
+                });
+            if let Ok(device) = second {
+                devices[1] = Some(device);
+                config |= SECOND_INTERRUPT;
+            }
+        }
+
+        controller.write_config(config)?;
+        Ok(devices[1].is_some_and(|device| device.is_mouse()))
+    })?;
+    // the BIOS may have left the mouse and the cascade of the second PIC
+    // masked
+    if mouse {
+        crate::interrupts::unmask_irq(2);
+        crate::interrupts::unmask_irq(12);
+    }
+    Ok(())
+}
+
+/// Returns the device found by `init` on `channel`.
+pub fn device(channel: Channel) -> Option<DeviceType> {
+    interrupts::without_interrupts(|| DEVICES.lock()[channel.index()])
+}
+
+/// Lights up the keyboard `leds`.
+pub async fn set_leds(leds: Leds) -> Result<(), Error> {
+    keyboard_command(&[SET_LEDS, leds.byte()]).await
+}
+
+/// Sets the key repeat of the keyboard.
+pub async fn set_typematic(typematic: Typematic) -> Result<(), Error> {
+    keyboard_command(&[SET_TYPEMATIC, typematic.byte()]).await
+}
+
+/// Called by the keyboard interrupt handler with the byte it read, returns
+/// `true` if it is the response to a `keyboard_command`, and must not be
+/// handled as a scancode.
+pub(crate) fn take_response(byte: u8) -> bool {
+    let response = (byte == ACK || byte == RESEND)
+        && RESPONSE
+            .compare_exchange(AWAITING_RESPONSE, byte as u16, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok();
+    if response {
+        RESPONSE_WAKER.wake();
+    }
+    response
+}
+
+/// Called by the timer interrupt handler, lets the command waiting for a
+/// response see that it timed out.
+pub(crate) fn tick() {
+    if RESPONSE.load(Ordering::Relaxed) == AWAITING_RESPONSE {
+        RESPONSE_WAKER.wake();
+    }
+}
+
+/// Sends `bytes` to the keyboard once `init` is done, each of them waiting
+/// for the keyboard to acknowledge it through `take_response`, so with
+/// interrupts enabled.
+async fn keyboard_command(bytes: &[u8]) -> Result<(), Error> {
+    if device(Channel::First).is_none() {
+        return Err(Error::NoKeyboard);
+    }
+    let _keyboard = KEYBOARD.lock().await;
+    for &byte in bytes {
+        send_to_keyboard(byte).await?;
+    }
+    Ok(())
+}
+
+async fn send_to_keyboard(byte: u8) -> Result<(), Error> {
+    for _ in 0..RESEND_LIMIT {
+        RESPONSE.store(AWAITING_RESPONSE, Ordering::Release);
+        interrupts::without_interrupts(|| CONTROLLER.lock().write_data(byte))?;
+
+        let deadline = crate::interrupts::ticks() + RESPONSE_TICKS;
+        let response = poll_fn(|cx| {
+            RESPONSE_WAKER.register(cx.waker());
+            match RESPONSE.load(Ordering::Acquire) {
+                AWAITING_RESPONSE if crate::interrupts::ticks() < deadline => Poll::Pending,
+                response => Poll::Ready(response),
+            }
+        }).await;
+        RESPONSE.store(NO_COMMAND, Ordering::Release);
+        match response {
+            AWAITING_RESPONSE => return Err(Error::Timeout),
+            response if response == ACK as u16 => return Ok(()),
+            _ => {}
+        }
+    }
+    Err(Error::NotAcknowledged(byte))
+}
+
+impl Controller {
+    const fn new() -> Self {
+        Self {
+            data: Port::new(0x60),
+            status: PortReadOnly::new(0x64),
+            command: PortWriteOnly::new(0x64),
+        }
+    }
+
+    fn wait_status(&mut self, mask: u8, set: bool) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            let status = unsafe { self.status.read() };
+            if (status & mask != 0) == set {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), Error> {
+        self.wait_status(INPUT_FULL, false)?;
+        unsafe { self.command.write(command) };
+        Ok(())
+    }
+
+    fn command_with_response(&mut self, command: u8) -> Result<u8, Error> {
+        self.command(command)?;
+        self.read_data()
+    }
+
+    fn read_data(&mut self) -> Result<u8, Error> {
+        self.wait_status(OUTPUT_FULL, true)?;
+        Ok(unsafe { self.data.read() })
+    }
+
+    fn write_data(&mut self, data: u8) -> Result<(), Error> {
+        self.wait_status(INPUT_FULL, false)?;
+        unsafe { self.data.write(data) };
+        Ok(())
+    }
+
+    /// Drops the bytes waiting in the output buffer.
+    fn flush(&mut self) {
+        while unsafe { self.status.read() } & OUTPUT_FULL != 0 {
+            unsafe { self.data.read() };
+        }
+    }
+
+    fn read_config(&mut self) -> Result<u8, Error> {
+        self.command_with_response(READ_CONFIG)
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), Error> {
+        self.command(WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    fn test_port(&mut self, channel: Channel) -> Result<(), Error> {
+        let test = match channel {
+            Channel::First => TEST_FIRST,
+            Channel::Second => TEST_SECOND,
+        };
+        match self.command_with_response(test)? {
+            PORT_TEST_PASSED => Ok(()),
+            result => Err(Error::PortTestFailed(channel, result)),
+        }
+    }
+
+    /// Sends `byte` to the device on `channel`.
+    fn send(&mut self, channel: Channel, byte: u8) -> Result<(), Error> {
+        if channel == Channel::Second {
+            self.command(WRITE_SECOND)?;
+        }
+        self.write_data(byte)
+    }
+
+    /// Sends `bytes` to the device on `channel`, each of them acknowledged.
+    fn device_command(&mut self, channel: Channel, bytes: &[u8]) -> Result<(), Error> {
+        for &byte in bytes {
+            self.send(channel, byte)?;
+            let mut tries = 0;
+            loop {
+                match self.read_data()? {
+                    ACK => break,
+                    _ if tries == RESEND_LIMIT => return Err(Error::NotAcknowledged(byte)),
+                    RESEND => self.send(channel, byte)?,
+                    // e.g. the ID a mouse sends after its reset
+                    _ => {}
+                }
+                tries += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resets the device on `channel` and identifies it, leaving it with
+    /// scanning disabled.
+    fn init_device(&mut self, channel: Channel) -> Result<DeviceType, Error> {
+        self.device_command(channel, &[RESET])?;
+        match self.read_data()? {
+            DEVICE_TEST_PASSED => {}
+            result => return Err(Error::DeviceTestFailed(channel, result)),
+        }
+
+        self.device_command(channel, &[DISABLE_SCANNING])?;
+        self.device_command(channel, &[IDENTIFY])?;
+        let first = self.read_data().ok();
+        let second = first.and_then(|_| self.read_data().ok());
+        Ok(DeviceType::from_id(first, second))
+    }
+
+    /// Unlocks the scroll wheel and the extra buttons of the mouse on the
+    /// second channel, if it has them, and enables its reports.
+    fn init_mouse(&mut self) -> Result<DeviceType, Error> {
+        let mut device = DeviceType::Mouse;
+        // magic sequences of sample rates, the mouse then changes its ID
+        for (rates, unlocked) in [
+            ([200, 100, 80], DeviceType::ScrollMouse),
+            ([200, 200, 80], DeviceType::FiveButtonMouse),
+        ] {
+            for rate in rates {
+                self.device_command(Channel::Second, &[SET_SAMPLE_RATE, rate])?;
+            }
+            self.device_command(Channel::Second, &[IDENTIFY])?;
+            match DeviceType::from_id(self.read_data().ok(), None) {
+                id if id == unlocked => device = unlocked,
+                _ => break,
+            }
+        }
+        self.device_command(Channel::Second, &[SET_SAMPLE_RATE, SAMPLE_RATE])?;
+        self.device_command(Channel::Second, &[ENABLE_SCANNING])?;
+        Ok(device)
+    }
+}
+
+impl Channel {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl DeviceType {
+    fn from_id(first: Option<u8>, second: Option<u8>) -> Self {
+        match (first, second) {
+            (None, _) => DeviceType::AtKeyboard,
+            (Some(0x00), _) => DeviceType::Mouse,
+            (Some(0x03), _) => DeviceType::ScrollMouse,
+            (Some(0x04), _) => DeviceType::FiveButtonMouse,
+            // the second byte is translated when the translation is on
+            (Some(0xab), Some(0x41 | 0x83 | 0xc1)) => DeviceType::Mf2Keyboard,
+            _ => DeviceType::Unknown,
+        }
+    }
+
+    pub fn is_keyboard(self) -> bool {
+        matches!(self, DeviceType::AtKeyboard | DeviceType::Mf2Keyboard)
+    }
+
+    pub fn is_mouse(self) -> bool {
+        matches!(self, DeviceType::Mouse | DeviceType::ScrollMouse | DeviceType::FiveButtonMouse)
+    }
+}
+
+impl Leds {
+    fn byte(self) -> u8 {
+        self.scroll_lock as u8 | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+}
+
+impl Typematic {
+    fn byte(self) -> u8 {
+        (self.delay & 0b11) << 5 | (self.rate & 0b1_1111)
+    }
+}
+
+impl Default for Leds {
+    /// Num Lock on, the state the keyboard decoder starts in
+    fn default() -> Self {
+        Self { scroll_lock: false, num_lock: true, caps_lock: false }
+    }
+}
+
+impl Default for Typematic {
+    /// 500 ms before repeating 10.9 times per second
+    fn default() -> Self {
+        Self { delay: 1, rate: 0x0b }
+    }
+}
+
+#[test_case]
+fn test_keyboard_detected() {
+    // QEMU emulates an MF2 keyboard, with a mouse on the second channel
+    assert!(device(Channel::First).is_some_and(DeviceType::is_keyboard));
+}
+
+#[test_case]
+fn test_mouse_extensions_detected() {
+    // QEMU emulates an IntelliMouse Explorer
+    assert_eq!(device(Channel::Second), Some(DeviceType::FiveButtonMouse));
+}
@@ -8,6 +8,8 @@ pub enum QemuExitCode {
 pub fn exit_qemu(exit_code: QemuExitCode) {
     use x86_64::instructions::port::Port;
 
+    // the buffered output would be lost
+    crate::serial::SERIAL1.flush();
     unsafe {
         let mut port = Port::new(0xf4);
         port.write(exit_code as u32);
@@ -1,22 +1,136 @@
+//! 16550 UART serial ports, COM1 to COM4.
+//!
+//! The bytes written go through a transmit buffer. Until `init` is called
+//! the buffer is drained right away, by polling the line status, then the
+//! "transmitter empty" interrupt drains it while the kernel goes on. It is
+//! still drained by polling when full, so that nothing is lost, and by
+//! `SerialPort::flush`.
+//!
+//! `init` also enables the "data received" interrupt of the ports present,
+//! whose bytes are read with `task::serial::SerialStream`.
+
 use core::fmt;
 
-use lazy_static::lazy_static;
 use spin::Mutex;
-use uart_16550::SerialPort;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+/// Registers, from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+/// Interrupt identification when read, FIFO control when written
+const INTERRUPT_ID: u16 = 2;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const MODEM_STATUS: u16 = 6;
+/// With the divisor latch access bit set, the two registers of the divisor
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+
+/// Bits of the interrupt enable register
+const DATA_RECEIVED: u8 = 1 << 0;
+const TRANSMITTER_EMPTY: u8 = 1 << 1;
+
+/// Bit of the interrupt identification register, clear while one is pending
+const NO_INTERRUPT: u8 = 1 << 0;
+/// Causes, in bits 1 to 3 of the interrupt identification register
+const CAUSE_MODEM_STATUS: u8 = 0b000;
+const CAUSE_TRANSMITTER_EMPTY: u8 = 0b001;
+const CAUSE_DATA_RECEIVED: u8 = 0b010;
+const CAUSE_LINE_STATUS: u8 = 0b011;
+const CAUSE_TIMEOUT: u8 = 0b110;
+
+/// Bits of the line status register
+const DATA_READY: u8 = 1 << 0;
+const TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Line control: 8 data bits, no parity, one stop bit, and the divisor
+/// latch access bit
+const EIGHT_N_ONE: u8 = 0x03;
+const DIVISOR_LATCH: u8 = 0x80;
+/// 38400 bauds
+const DIVISOR: u16 = 3;
+/// Enables and clears the FIFOs, interrupting at 14 received bytes
+const FIFO_SETTINGS: u8 = 0xc7;
+/// Modem control: data terminal ready, request to send, and OUT2, which
+/// routes the interrupts to the PIC, plus loopback for the detection test
+const MODEM_READY: u8 = 0x0b;
+const LOOPBACK: u8 = 0x1e;
+/// Byte sent to itself in loopback mode to detect the port
+const LOOPBACK_TEST: u8 = 0xae;
+
+/// Bytes written to the transmit FIFO at once
+const FIFO_SIZE: usize = 16;
+/// Bytes waiting to be sent, per port
+const TRANSMIT_BUFFER_SIZE: usize = 1024;
+
+/// The ports, by `Com` index
+///
+/// Only locked with interrupts disabled.
+static UARTS: [Mutex<Uart>; 4] = [
+    Mutex::new(Uart::new(Com::Com1)),
+    Mutex::new(Uart::new(Com::Com2)),
+    Mutex::new(Uart::new(Com::Com3)),
+    Mutex::new(Uart::new(Com::Com4)),
+];
+
+/// COM1, that the `serial_print!` macros write to
+pub const SERIAL1: SerialPort = SerialPort { com: Com::Com1 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Com {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
 
-lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No UART answered at the port
+    NotPresent,
 }
 
+/// A serial port to write to, copies share its transmit buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialPort {
+    com: Com,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Not set up yet
+    Uninitialized,
+    /// No UART answered
+    Absent,
+    /// Transmit buffer drained by polling
+    Polled,
+    /// Interrupts enabled by `init`
+    Interrupts,
+}
+
+struct Uart {
+    com: Com,
+    base: u16,
+    state: State,
+    /// Interrupts enabled
+    enabled: u8,
+    buffer: TransmitBuffer,
+}
+
+/// Ring buffer of the bytes to send
+struct TransmitBuffer {
+    bytes: [u8; TRANSMIT_BUFFER_SIZE],
+    start: usize,
+    len: usize,
+}
 
 pub struct Green(pub &'static str);
 
 impl fmt::Display for Green {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { 
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "\x1B[32m")?; // prefix code
         write!(f, "{}", self.0)?;
         write!(f, "\x1B[0m")?; // postfix code
@@ -27,7 +141,7 @@ impl fmt::Display for Green {
 pub struct Red(pub &'static str);
 
 impl fmt::Display for Red {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { 
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "\x1B[31m")?; // prefix code
         write!(f, "{}", self.0)?;
         write!(f, "\x1B[0m")?; // postfix code
@@ -35,13 +149,280 @@ impl fmt::Display for Red {
     }
 }
 
+/// Enables the interrupts of the ports present, and unmasks their IRQs.
+pub fn init() {
+    for uart in &UARTS {
+        let irq = interrupts::without_interrupts(|| {
+            let mut uart = uart.lock();
+            if !uart.detect() {
+                return None;
+            }
+            uart.state = State::Interrupts;
+            uart.enable(DATA_RECEIVED);
+            Some(uart.com.irq())
+        });
+        if let Some(irq) = irq {
+            crate::interrupts::unmask_irq(irq);
+        }
+    }
+}
+
+/// Called by the interrupt handler of `irq`, serves the ports using it.
+pub(crate) fn handle_interrupt(irq: u8) {
+    for com in [Com::Com1, Com::Com2, Com::Com3, Com::Com4] {
+        if com.irq() == irq {
+            UARTS[com as usize].lock().handle_interrupt();
+        }
+    }
+}
+
+impl Com {
+    const fn base(self) -> u16 {
+        match self {
+            Com::Com1 => 0x3f8,
+            Com::Com2 => 0x2f8,
+            Com::Com3 => 0x3e8,
+            Com::Com4 => 0x2e8,
+        }
+    }
+
+    /// IRQ of the port, shared by COM1 and COM3, and by COM2 and COM4
+    pub fn irq(self) -> u8 {
+        match self {
+            Com::Com1 | Com::Com3 => 4,
+            Com::Com2 | Com::Com4 => 3,
+        }
+    }
+}
+
+impl SerialPort {
+    /// Returns port `com`, if there is a UART there.
+    pub fn open(com: Com) -> Result<Self, Error> {
+        let present = interrupts::without_interrupts(|| UARTS[com as usize].lock().detect());
+        match present {
+            true => Ok(Self { com }),
+            false => Err(Error::NotPresent),
+        }
+    }
+
+    pub fn com(self) -> Com {
+        self.com
+    }
+
+    /// Queues `bytes` to be sent, waiting only if the transmit buffer is
+    /// full.
+    pub fn write(self, bytes: &[u8]) {
+        interrupts::without_interrupts(|| UARTS[self.com as usize].lock().write(bytes));
+    }
+
+    /// Waits until the transmit buffer is empty.
+    pub fn flush(self) {
+        interrupts::without_interrupts(|| UARTS[self.com as usize].lock().drain());
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl Uart {
+    const fn new(com: Com) -> Self {
+        Self {
+            com,
+            base: com.base(),
+            state: State::Uninitialized,
+            enabled: 0,
+            buffer: TransmitBuffer::new(),
+        }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        let mut port: Port<u8> = Port::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        let mut port: Port<u8> = Port::new(self.base + register);
+        unsafe { port.write(value) };
+    }
+
+    /// Sets the UART up the first time, returns whether there is one.
+    fn detect(&mut self) -> bool {
+        if self.state == State::Uninitialized {
+            self.state = match self.setup() {
+                true => State::Polled,
+                false => State::Absent,
+            };
+        }
+        self.state != State::Absent
+    }
+
+    /// Sets the line up without interrupts, and checks that the UART sends
+    /// to itself in loopback mode.
+    fn setup(&mut self) -> bool {
+        self.write_register(INTERRUPT_ENABLE, 0);
+        self.write_register(LINE_CONTROL, DIVISOR_LATCH);
+        self.write_register(DIVISOR_LOW, DIVISOR as u8);
+        self.write_register(DIVISOR_HIGH, (DIVISOR >> 8) as u8);
+        self.write_register(LINE_CONTROL, EIGHT_N_ONE);
+        self.write_register(FIFO_CONTROL, FIFO_SETTINGS);
+        self.write_register(MODEM_CONTROL, LOOPBACK);
+        self.write_register(DATA, LOOPBACK_TEST);
+        if self.read(DATA) != LOOPBACK_TEST {
+            return false;
+        }
+        self.write_register(MODEM_CONTROL, MODEM_READY);
+        true
+    }
+
+    fn enable(&mut self, interrupts: u8) {
+        self.enabled |= interrupts;
+        self.write_register(INTERRUPT_ENABLE, self.enabled);
+    }
+
+    fn disable(&mut self, interrupts: u8) {
+        self.enabled &= !interrupts;
+        self.write_register(INTERRUPT_ENABLE, self.enabled);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if !self.detect() {
+            return;
+        }
+        for &byte in bytes {
+            if self.buffer.is_full() {
+                self.send_polled();
+            }
+            self.buffer.push(byte);
+        }
+        match self.state {
+            State::Interrupts => {
+                self.fill_fifo();
+                if !self.buffer.is_empty() {
+                    self.enable(TRANSMITTER_EMPTY);
+                }
+            }
+            _ => self.drain(),
+        }
+    }
+
+    /// Sends the buffered bytes, polling the line status.
+    fn drain(&mut self) {
+        while !self.buffer.is_empty() {
+            self.send_polled();
+        }
+    }
+
+    /// Sends the oldest buffered byte once the UART can take it.
+    fn send_polled(&mut self) {
+        while self.read(LINE_STATUS) & TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        if let Some(byte) = self.buffer.pop() {
+            self.write_register(DATA, byte);
+        }
+    }
+
+    /// Moves buffered bytes to the transmit FIFO, if it is empty.
+    fn fill_fifo(&mut self) {
+        if self.read(LINE_STATUS) & TRANSMIT_EMPTY == 0 {
+            return;
+        }
+        for _ in 0..FIFO_SIZE {
+            match self.buffer.pop() {
+                Some(byte) => self.write_register(DATA, byte),
+                None => break,
+            }
+        }
+    }
+
+    /// Serves every pending interrupt of the UART.
+    fn handle_interrupt(&mut self) {
+        if self.state != State::Interrupts {
+            return;
+        }
+        loop {
+            let id = self.read(INTERRUPT_ID);
+            if id & NO_INTERRUPT != 0 {
+                break;
+            }
+            match (id >> 1) & 0b111 {
+                CAUSE_DATA_RECEIVED | CAUSE_TIMEOUT => {
+                    while self.read(LINE_STATUS) & DATA_READY != 0 {
+                        let byte = self.read(DATA);
+                        crate::task::serial::add_byte(self.com, byte);
+                    }
+                }
+                CAUSE_TRANSMITTER_EMPTY => {
+                    self.fill_fifo();
+                    if self.buffer.is_empty() {
+                        self.disable(TRANSMITTER_EMPTY);
+                    }
+                }
+                // reading the status clears them
+                CAUSE_LINE_STATUS => {
+                    self.read(LINE_STATUS);
+                }
+                CAUSE_MODEM_STATUS => {
+                    self.read(MODEM_STATUS);
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+impl fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl TransmitBuffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; TRANSMIT_BUFFER_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == TRANSMIT_BUFFER_SIZE
+    }
+
+    /// Adds `byte` at the end, the buffer must not be full.
+    fn push(&mut self, byte: u8) {
+        self.bytes[(self.start + self.len) % TRANSMIT_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.start];
+        self.start = (self.start + 1) % TRANSMIT_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
+    // locked for the whole message, so that it is not mixed with others
     interrupts::without_interrupts(|| {
-        SERIAL1.lock()
+        UARTS[SERIAL1.com as usize].lock()
             .write_fmt(args)
             .expect("Printing to serial failed");
     });
@@ -59,4 +440,23 @@ macro_rules! serial_println {
     ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
 }
 
+#[test_case]
+fn test_first_port_detected() {
+    assert_eq!(SerialPort::open(Com::Com1), Ok(SERIAL1));
+}
 
+#[test_case]
+fn test_transmit_buffer_wraps_around() {
+    let mut buffer = TransmitBuffer::new();
+    for round in 0..3 {
+        for i in 0..TRANSMIT_BUFFER_SIZE - 1 {
+            buffer.push((round + i) as u8);
+        }
+        assert!(!buffer.is_full());
+        for i in 0..TRANSMIT_BUFFER_SIZE - 1 {
+            assert_eq!(buffer.pop(), Some((round + i) as u8));
+        }
+        assert!(buffer.is_empty());
+    }
+    assert_eq!(buffer.pop(), None);
+}
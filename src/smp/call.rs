@@ -0,0 +1,104 @@
+//! Cross-calls: running a function on other CPUs.
+//!
+//! The function is queued for each target CPU, which is then interrupted
+//! with `CALL_VECTOR` and runs its queue from the interrupt handler. The
+//! caller waits until every target is done, running the calls queued for
+//! its own CPU meanwhile, so that two CPUs calling each other do not wait
+//! forever.
+//!
+//! The functions run in interrupt context. Cross-calls must not be made
+//! while holding a spin lock, since a target may be spinning on it with
+//! interrupts disabled.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use super::{apic_id, cpu_count, cpu_index, MAX_CPUS};
+use crate::apic;
+
+/// Vector of the interrupt telling a CPU to run its queued calls
+pub const CALL_VECTOR: u8 = 0x41;
+
+struct Call {
+    function: Box<dyn Fn() + Send + Sync>,
+    /// Number of targets that did not run `function` yet
+    remaining: AtomicUsize,
+}
+
+/// Calls to run by each CPU
+///
+/// Only locked with interrupts disabled.
+static QUEUES: [Mutex<VecDeque<Arc<Call>>>; MAX_CPUS] =
+    [const { Mutex::new(VecDeque::new()) }; MAX_CPUS];
+
+/// Runs `function` on the CPU of index `cpu`, and waits for it to return.
+///
+/// Runs it directly if `cpu` is the running CPU.
+pub fn call_on<F>(cpu: usize, function: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    assert!(cpu < cpu_count(), "CPU {} is not running", cpu);
+    if cpu == cpu_index() {
+        function();
+    } else {
+        call(&[cpu], function);
+    }
+}
+
+/// Runs `function` on all the other running CPUs, and waits for them.
+pub fn call_on_others<F>(function: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let this_cpu = cpu_index();
+    let targets: Vec<usize> = (0..cpu_count())
+        .filter(|&cpu| cpu != this_cpu)
+        .collect();
+    if !targets.is_empty() {
+        call(&targets, function);
+    }
+}
+
+fn call<F>(targets: &[usize], function: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let call = Arc::new(Call {
+        function: Box::new(function),
+        remaining: AtomicUsize::new(targets.len()),
+    });
+    for &cpu in targets {
+        interrupts::without_interrupts(|| {
+            QUEUES[cpu].lock().push_back(call.clone());
+        });
+        apic::send_ipi(apic_id(cpu), CALL_VECTOR);
+    }
+    while call.remaining.load(Ordering::Acquire) != 0 {
+        run_queued_calls();
+        core::hint::spin_loop();
+    }
+}
+
+/// Runs the calls queued for the running CPU.
+fn run_queued_calls() {
+    let queue = &QUEUES[cpu_index()];
+    while let Some(call) = interrupts::without_interrupts(|| queue.lock().pop_front()) {
+        (call.function)();
+        call.remaining.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub(crate) extern "x86-interrupt" fn call_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    run_queued_calls();
+    apic::end_of_interrupt();
+}
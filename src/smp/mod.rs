@@ -0,0 +1,190 @@
+//! Symmetric multiprocessing.
+//!
+//! The bootstrap processor (BSP) runs `kernel_main`. `start_aps` boots the
+//! other CPUs listed in the ACPI MADT, the application processors (APs),
+//! one after the other with the INIT-SIPI-SIPI sequence. Each AP goes
+//! through `trampoline` to `ap_entry`, which gives it its own GDT, TSS,
+//! per-CPU data, local APIC timer and thread scheduler before calling the
+//! `main` given to `start_aps`.
+//!
+//! The per-CPU data of the running CPU is pointed to by the GS base.
+//!
+//! The CPUs can run functions on each other with `call_on` and
+//! `call_on_others`, see `call`.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use spin::Once;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::GsBase;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::{acpi, apic, gdt, memory, thread};
+
+pub(crate) mod call;
+mod trampoline;
+
+pub use call::{call_on, call_on_others, CALL_VECTOR};
+
+/// Maximum number of CPUs that can be started
+pub const MAX_CPUS: usize = 16;
+
+/// Size of the stack of each AP, on which its boot thread runs
+const AP_STACK_SIZE: usize = 64 * 1024;
+
+/// How many ticks of the timer to wait for an AP before giving up
+const AP_START_TIMEOUT: u64 = 100;
+
+/// Delays of the INIT-SIPI-SIPI sequence: 10 ms after INIT and 200 µs
+/// after each SIPI
+const INIT_DELAY_MILLIS: u64 = 10;
+const STARTUP_DELAY_MICROS: u64 = 200;
+
+/// Data owned by each CPU
+#[derive(Debug)]
+pub struct PerCpu {
+    /// From 0 for the BSP to `cpu_count() - 1`
+    pub index: usize,
+    pub apic_id: u32,
+}
+
+static BSP_PER_CPU: Once<PerCpu> = Once::new();
+
+/// Number of CPUs that are running, the BSP included
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+/// APIC ID of each running CPU, by index
+static APIC_IDS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(0) }; MAX_CPUS];
+
+/// Set by an AP when it is done with the trampoline
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Called by each AP once it is initialized
+static AP_MAIN: Once<fn() -> !> = Once::new();
+
+/// Sets up the per-CPU data of the bootstrap processor.
+pub fn init() {
+    // the local APIC is not mapped yet, take its ID from CPUID
+    let apic_id = core::arch::x86_64::__cpuid(1).ebx >> 24;
+    APIC_IDS[0].store(apic_id, Ordering::Relaxed);
+    set_per_cpu(BSP_PER_CPU.call_once(|| PerCpu { index: 0, apic_id }));
+}
+
+fn set_per_cpu(per_cpu: &'static PerCpu) {
+    GsBase::write(VirtAddr::from_ptr(per_cpu));
+}
+
+/// Returns the data of the running CPU, `None` before `init`.
+pub fn this_cpu() -> Option<&'static PerCpu> {
+    let per_cpu = GsBase::read();
+    if per_cpu.is_null() {
+        None
+    } else {
+        Some(unsafe { &*per_cpu.as_ptr() })
+    }
+}
+
+/// Returns the index of the running CPU, 0 before `init`.
+pub fn cpu_index() -> usize {
+    this_cpu().map_or(0, |cpu| cpu.index)
+}
+
+/// Returns the number of CPUs that are running.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+/// Returns the APIC ID of the CPU of index `cpu`.
+pub fn apic_id(cpu: usize) -> u32 {
+    APIC_IDS[cpu].load(Ordering::Relaxed)
+}
+
+/// Starts the application processors, each of them calls `main` once it is
+/// initialized. Returns the number of CPUs running.
+///
+/// Must be called once on the BSP, with interrupts enabled, after
+/// `memory::init_kernel_memory` and `thread::init`.
+pub fn start_aps(main: fn() -> !) -> usize {
+    assert!(interrupts::are_enabled(), "interrupts needed to time the AP startup");
+    assert!(AP_MAIN.get().is_none(), "application processors already started");
+    AP_MAIN.call_once(|| main);
+
+    let madt = match acpi::madt() {
+        Some(madt) => madt,
+        None => return cpu_count(),
+    };
+    apic::init(madt.local_apic_address);
+    let bsp_apic_id = apic::id();
+
+    // the trampoline keeps running once paging is on
+    let trampoline = PhysAddr::new(trampoline::TRAMPOLINE_ADDRESS);
+    memory::identity_map(PhysFrame::containing_address(trampoline));
+    let page_table = Cr3::read().0.start_address().as_u64();
+    assert!(page_table < 1 << 32, "page table out of reach of the trampoline");
+
+    for processor in madt.processors {
+        let apic_id = processor.apic_id as u32;
+        if apic_id == bsp_apic_id {
+            continue;
+        }
+        let index = cpu_count();
+        if index == MAX_CPUS {
+            break;
+        }
+
+        let stack = Box::leak(vec![0u8; AP_STACK_SIZE].into_boxed_slice());
+        let per_cpu = Box::leak(Box::new(PerCpu { index, apic_id }));
+        unsafe {
+            trampoline::install(trampoline::Params {
+                page_table,
+                stack_top: (stack.as_ptr_range().end as u64) & !0xf,
+                entry: ap_entry as *const () as u64,
+                argument: per_cpu as *const PerCpu as u64,
+            });
+        }
+
+        AP_STARTED.store(false, Ordering::Release);
+        apic::send_init(apic_id);
+        apic::wait_millis(INIT_DELAY_MILLIS);
+        // the second SIPI is only needed if the first one was missed
+        for _ in 0..2 {
+            apic::send_startup(apic_id, (trampoline.as_u64() >> 12) as u8);
+            apic::wait_micros(STARTUP_DELAY_MICROS);
+            if AP_STARTED.load(Ordering::Acquire) {
+                break;
+            }
+        }
+        let deadline = crate::interrupts::ticks() + AP_START_TIMEOUT;
+        while !AP_STARTED.load(Ordering::Acquire) && crate::interrupts::ticks() < deadline {
+            x86_64::instructions::hlt();
+        }
+        if AP_STARTED.load(Ordering::Acquire) {
+            APIC_IDS[index].store(apic_id, Ordering::Relaxed);
+            CPU_COUNT.fetch_add(1, Ordering::AcqRel);
+        } else {
+            crate::eprintln!("CPU with APIC ID {} did not start", apic_id);
+        }
+    }
+    cpu_count()
+}
+
+/// First Rust code run by an AP, called by the trampoline with its
+/// `PerCpu` and interrupts disabled.
+extern "C" fn ap_entry(per_cpu: *const PerCpu) -> ! {
+    let per_cpu = unsafe { &*per_cpu };
+    gdt::init_ap();
+    crate::interrupts::init_idt();
+    set_per_cpu(per_cpu);
+    apic::init_ap();
+    thread::init();
+
+    // the BSP can reuse the trampoline from now on
+    AP_STARTED.store(true, Ordering::Release);
+    interrupts::enable();
+    let main = AP_MAIN.get().expect("AP started without a main");
+    main()
+}
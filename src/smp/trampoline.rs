@@ -0,0 +1,124 @@
+//! Real mode code started by the SIPI on the application processors.
+//!
+//! It is copied to `TRAMPOLINE_ADDRESS` (the SIPI can only start a CPU in
+//! the first MiB), goes through protected mode to long mode with the page
+//! table of the bootstrap processor, and calls the entry point stored in
+//! its parameters on the stack stored there. The code must not depend on
+//! where it was linked, every address is computed relative to
+//! `TRAMPOLINE_ADDRESS`.
+
+use core::arch::global_asm;
+use core::ptr::copy_nonoverlapping;
+
+use x86_64::PhysAddr;
+
+use crate::memory::phys_to_virt;
+
+/// Physical address the trampoline runs at, page aligned
+pub const TRAMPOLINE_ADDRESS: u64 = 0x8000;
+
+global_asm!(
+    ".set TRAMPOLINE, {address}",
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_end",
+    ".global ap_trampoline_params",
+    ".code16",
+    "ap_trampoline_start:",
+    "cli",
+    "cld",
+    "xor %ax, %ax",
+    "mov %ax, %ds",
+    "lgdtl (TRAMPOLINE + (ap_trampoline_gdt_pointer - ap_trampoline_start))",
+    // protected mode
+    "mov %cr0, %eax",
+    "or $1, %eax",
+    "mov %eax, %cr0",
+    "ljmpl $0x08, $(TRAMPOLINE + (ap_trampoline_32 - ap_trampoline_start))",
+    ".code32",
+    "ap_trampoline_32:",
+    "mov $0x10, %ax",
+    "mov %ax, %ds",
+    "mov %ax, %es",
+    "mov %ax, %ss",
+    // physical address extension, needed by long mode
+    "mov %cr4, %eax",
+    "or $(1 << 5), %eax",
+    "mov %eax, %cr4",
+    "mov (TRAMPOLINE + (ap_trampoline_params - ap_trampoline_start)), %eax",
+    "mov %eax, %cr3",
+    // long mode and no-execute, which the bootloader's page table uses
+    "mov $0xc0000080, %ecx",
+    "rdmsr",
+    "or $((1 << 8) | (1 << 11)), %eax",
+    "wrmsr",
+    // paging and write protection
+    "mov %cr0, %eax",
+    "or $((1 << 31) | (1 << 16)), %eax",
+    "mov %eax, %cr0",
+    "ljmpl $0x18, $(TRAMPOLINE + (ap_trampoline_64 - ap_trampoline_start))",
+    ".code64",
+    "ap_trampoline_64:",
+    "xor %ax, %ax",
+    "mov %ax, %ds",
+    "mov %ax, %es",
+    "mov %ax, %ss",
+    "mov %ax, %fs",
+    "mov %ax, %gs",
+    "mov (TRAMPOLINE + (ap_trampoline_params - ap_trampoline_start) + 8), %rsp",
+    "mov (TRAMPOLINE + (ap_trampoline_params - ap_trampoline_start) + 24), %rdi",
+    "mov (TRAMPOLINE + (ap_trampoline_params - ap_trampoline_start) + 16), %rax",
+    "call *%rax",
+    "2:",
+    "hlt",
+    "jmp 2b",
+    // null, 32 bits code, data, 64 bits code
+    ".p2align 3",
+    "ap_trampoline_gdt:",
+    ".quad 0",
+    ".quad 0x00cf9a000000ffff",
+    ".quad 0x00cf92000000ffff",
+    ".quad 0x00af9a000000ffff",
+    "ap_trampoline_gdt_pointer:",
+    ".word ap_trampoline_gdt_pointer - ap_trampoline_gdt - 1",
+    ".long TRAMPOLINE + (ap_trampoline_gdt - ap_trampoline_start)",
+    // see `Params`
+    ".p2align 3",
+    "ap_trampoline_params:",
+    ".quad 0, 0, 0, 0",
+    "ap_trampoline_end:",
+    address = const TRAMPOLINE_ADDRESS,
+    options(att_syntax),
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_params: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Parameters read by the trampoline, at `ap_trampoline_params`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Params {
+    /// Physical address of the level 4 page table, below 4 GiB since it is
+    /// loaded in protected mode
+    pub page_table: u64,
+    pub stack_top: u64,
+    /// `extern "C" fn(u64) -> !` called with `argument`
+    pub entry: u64,
+    pub argument: u64,
+}
+
+/// Copies the trampoline to `TRAMPOLINE_ADDRESS`, with `params`.
+///
+/// Safety: the memory at `TRAMPOLINE_ADDRESS` must not be in use, and no
+/// application processor may be running the trampoline.
+pub unsafe fn install(params: Params) {
+    let start = &ap_trampoline_start as *const u8;
+    let end = &ap_trampoline_end as *const u8;
+    let params_offset = (&ap_trampoline_params as *const u8).offset_from(start);
+
+    let destination = phys_to_virt(PhysAddr::new(TRAMPOLINE_ADDRESS)).as_mut_ptr::<u8>();
+    copy_nonoverlapping(start, destination, end.offset_from(start) as usize);
+    (destination.offset(params_offset) as *mut Params).write_volatile(params);
+}
@@ -1,82 +1,324 @@
+use core::array;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use core::task::{Waker, Context, Poll};
 
-use alloc::{collections::BTreeMap, sync::Arc, format, task::Wake, string::String};
+use alloc::{collections::{BTreeMap, VecDeque}, format, string::String, sync::Arc, task::Wake, vec::Vec};
 use crossbeam_queue::ArrayQueue;
-use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 
-use super::{TaskId, Task};
+use super::{TaskId, Task, Priority};
+use crate::smp::{self, MAX_CPUS};
 
-/// aka MAX_TASKS
-const TASK_QUEUE_CAPACITY: usize = 100;
+/// Number of tasks a `Spawner` can queue without allocating before the
+/// executors pick them up
+const NEW_TASKS_CAPACITY: usize = 64;
 
-lazy_static!{
-    static ref TASK_QUEUE_FULL_MESSAGE: String = format!(
-    "Task Queue full. Increase TASK_QUEUE_CAPACITY (currently {TASK_QUEUE_CAPACITY})");
-}
+/// Number of polls each priority class gets per round, from the highest
+/// priority to the lowest.
+///
+/// A class that runs out of budget has to wait for the next round, so a
+/// task that keeps waking itself can not starve the other classes.
+const ROUND_BUDGETS: [usize; Priority::COUNT] = [8, 4, 1];
+
+/// Value of `Shared::current_tasks` between polls
+const NO_TASK: u64 = u64::MAX;
 
+/// Values of `TaskWaker::last_wake` that are not task IDs
+const WOKEN_BY_SPAWN: u64 = u64::MAX;
+const WOKEN_EXTERNALLY: u64 = u64::MAX - 1;
+
+/// Runs `Task`s to completion.
+///
+/// Ready tasks are kept in one FIFO per `Priority`. Tasks are polled in
+/// rounds: the highest priority class that still has a ready task and
+/// some budget left (see `ROUND_BUDGETS`) is polled first, and the round
+/// ends once every class is either idle or out of budget.
+///
+/// On SMP, each CPU can run an executor that shares its tasks with the
+/// others (see `Executor::join`). Every CPU has its own ready queues, and
+/// a task is queued on the CPU that owns it when woken. An executor that
+/// has nothing left to poll steals ready tasks from the other CPUs, and
+/// becomes their owner.
 pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    /// Index of the CPU running the executor, and of its ready queues
+    cpu: usize,
+    shared: Arc<Shared>,
+}
+
+/// A cloneable handle that spawns tasks on an `Executor`.
+///
+/// Unlike `Executor::spawn`, it does not need `&mut Executor`, so it can be
+/// moved into running tasks to let them spawn child tasks. New tasks go
+/// through a preallocated lock-free queue that the executors drain between
+/// polls, so `try_spawn` never blocks or allocates, even from an interrupt
+/// handler. `spawn` queues the tasks that do not fit apart instead.
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+/// State shared by the executors of all CPUs, their spawners and wakers
+struct Shared {
+    /// The ready queues of each CPU
+    ready_queues: [ReadyQueue; MAX_CPUS],
+    /// Tasks queued by the spawners, see `NEW_TASKS_CAPACITY`
+    new_tasks: ArrayQueue<Task>,
+    /// Tasks queued by `Spawner::spawn` while `new_tasks` was full
+    ///
+    /// Only locked with interrupts disabled.
+    overflow_tasks: Mutex<VecDeque<Task>>,
+    /// Every live task
+    tasks: Mutex<BTreeMap<TaskId, Arc<TaskWaker>>>,
+    /// ID of the task being polled by each CPU, or `NO_TASK`
+    current_tasks: [AtomicU64; MAX_CPUS],
+    /// Number of executors sharing this state
+    executors: AtomicUsize,
+}
+
+/// FIFOs of the IDs of the tasks that are ready to be polled,
+/// one per priority class.
+///
+/// A task is queued at most once at a time (see `TaskWaker::scheduled`)
+/// and the executors reserve one slot per task, so pushing never
+/// allocates. Together with the lock being taken with interrupts disabled,
+/// this makes waking a task safe from interrupt handlers.
+struct ReadyQueue {
+    queues: Mutex<[VecDeque<TaskId>; Priority::COUNT]>,
 }
 
+/// Per task state shared between the executors and the task's wakers
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    priority: Priority,
+    name: Option<String>,
+    /// `None` once the task completed. Locked by the executor polling the
+    /// task, the others only try to lock it.
+    task: Mutex<Option<Task>>,
+    /// CPU whose ready queues the task is pushed to when woken
+    owner: AtomicUsize,
+    /// Set while the task is in a ready queue, so that waking it
+    /// again before it is polled does not queue it twice.
+    scheduled: AtomicBool,
+    shared: Arc<Shared>,
+    polls: AtomicU64,
+    poll_cycles: AtomicU64,
+    /// Encoded `WakeSource` of the last wake
+    last_wake: AtomicU64,
+}
+
+/// Snapshot of a live task, as returned by `Executor::tasks`.
+///
+/// Displays as a single line, for debug consoles.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub priority: Priority,
+    pub state: TaskState,
+    /// Number of times the task has been polled
+    pub polls: u64,
+    /// Total time spent polling the task, in CPU cycles
+    pub poll_cycles: u64,
+    pub last_wake: WakeSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// In the ready queue, waiting for its turn
+    Queued,
+    /// Being polled right now
+    Running,
+    /// Waiting for a waker to be called
+    Pending,
+}
+
+/// What made a task ready the last time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    /// Never woken, queued since it was spawned
+    Spawn,
+    /// Woke itself while being polled (e.g. to yield)
+    Itself,
+    /// Woken by another task while it was being polled
+    Task(TaskId),
+    /// Woken outside of any poll, usually by an interrupt handler
+    External,
 }
 
 impl Executor {
+    /// Creates an executor for the running CPU, that shares its tasks with
+    /// no other executor.
     pub fn new() -> Self {
-        Self { 
-            tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
-            waker_cache: BTreeMap::new(),
-        }
+        let shared = Arc::new(Shared {
+            ready_queues: array::from_fn(|_| ReadyQueue::new()),
+            new_tasks: ArrayQueue::new(NEW_TASKS_CAPACITY),
+            overflow_tasks: Mutex::new(VecDeque::new()),
+            tasks: Mutex::new(BTreeMap::new()),
+            current_tasks: [const { AtomicU64::new(NO_TASK) }; MAX_CPUS],
+            executors: AtomicUsize::new(0),
+        });
+        Self::with_cpu(shared, smp::cpu_index())
     }
 
-    pub fn spawn(&mut self, task: Task) {
+    /// Creates an executor for the running CPU, that shares its tasks with
+    /// the executor of `spawner`. There should be one executor per CPU.
+    pub fn join(spawner: &Spawner) -> Self {
+        Self::with_cpu(spawner.shared.clone(), smp::cpu_index())
+    }
+
+    fn with_cpu(shared: Arc<Shared>, cpu: usize) -> Self {
+        // `spawn` already reserved room in our ready queues
+        shared.executors.fetch_add(1, Ordering::Relaxed);
+        Self { cpu, shared }
+    }
+
+    pub fn spawn(&mut self, mut task: Task) {
         let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("task with same ID ({:?}) already in tasks", task_id);
+        let priority = task.priority;
+        let name = task.name.take();
+
+        // new tasks start scheduled
+        let waker = TaskWaker::new(task, priority, name, self.cpu, self.shared.clone());
+        let tasks = interrupts::without_interrupts(|| {
+            let mut tasks = self.shared.tasks.lock();
+            if tasks.insert(task_id, waker).is_some() {
+                panic!("task with same ID ({:?}) already in tasks", task_id);
+            }
+            tasks.len()
+        });
+        // any CPU may end up owning it, even one still being started
+        for ready_queue in &self.shared.ready_queues {
+            ready_queue.reserve(tasks);
+        }
+        self.shared.ready_queues[self.cpu].push(task_id, priority);
+    }
+
+    /// Returns a handle that can spawn tasks on this executor
+    /// from inside its running tasks.
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Lists the live tasks of the executor.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.shared.tasks()
+    }
+
+    /// Moves the tasks pushed through a `Spawner` into the executor.
+    fn spawn_new_tasks(&mut self) {
+        while let Some(task) = self.shared.new_tasks.pop() {
+            self.spawn(task);
+        }
+        let overflow = interrupts::without_interrupts(|| {
+            core::mem::take(&mut *self.shared.overflow_tasks.lock())
+        });
+        for task in overflow {
+            self.spawn(task);
         }
-        self.task_queue.push(task_id).expect(&TASK_QUEUE_FULL_MESSAGE);
     }
 
+    /// Runs one scheduling round.
     fn run_ready_tasks(&mut self) {
-        while let Some(task_id) = self.task_queue.pop() {
-            let task = match self.tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue,
-            };
-            let waker = self.waker_cache
-                .entry(task_id)
-                .or_insert_with(|| 
-                    TaskWaker::new_waker(task_id, self.task_queue.clone())
-                );
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    // task done so remove it and its cached waker
-                    self.tasks.remove(&task_id);
-                    self.waker_cache.remove(&task_id);
+        let mut budgets = ROUND_BUDGETS;
+        loop {
+            // tasks spawned by the previous poll run in the same round
+            self.spawn_new_tasks();
+
+            let task_id = match self.shared.ready_queues[self.cpu].pop(&mut budgets) {
+                Some(task_id) => task_id,
+                None => match self.steal(&mut budgets) {
+                    Some(task_id) => task_id,
+                    None => break,
                 },
-                Poll::Pending => {},
+            };
+            self.poll_task(task_id);
+        }
+    }
+
+    /// Pops a ready task from the queues of another CPU.
+    fn steal(&self, budgets: &mut [usize; Priority::COUNT]) -> Option<TaskId> {
+        // start after our CPU, so that the thieves spread over the victims
+        let cpus = smp::cpu_count().max(self.cpu + 1);
+        (1..cpus)
+            .map(|offset| (self.cpu + offset) % cpus)
+            .find_map(|victim| self.shared.ready_queues[victim].pop(budgets))
+    }
+
+    fn poll_task(&mut self, task_id: TaskId) {
+        let task_waker = interrupts::without_interrupts(|| {
+            self.shared.tasks.lock().get(&task_id).cloned()
+        });
+        let task_waker = match task_waker {
+            Some(task_waker) => task_waker,
+            None => return,
+        };
+        let mut guard = match task_waker.task.try_lock() {
+            Some(guard) => guard,
+            None => {
+                // stolen while its owner polls it, the owner polls it again
+                let owner = task_waker.owner.load(Ordering::Acquire);
+                self.shared.ready_queues[owner].push(task_id, task_waker.priority);
+                return;
             }
+        };
+        let task = match guard.as_mut() {
+            Some(task) => task,
+            None => return,
+        };
+        // wakes during the poll are routed to us from now on
+        task_waker.owner.store(self.cpu, Ordering::Release);
+        // cleared before polling so that wakes during the poll requeue it
+        task_waker.scheduled.store(false, Ordering::Release);
+
+        let waker = Waker::from(task_waker.clone());
+        let mut context = Context::from_waker(&waker);
+        let current_task = &self.shared.current_tasks[self.cpu];
+        current_task.store(task_id.0, Ordering::Relaxed);
+        let start = cycles();
+        let poll = task.poll(&mut context);
+        task_waker.poll_cycles.fetch_add(cycles() - start, Ordering::Relaxed);
+        task_waker.polls.fetch_add(1, Ordering::Relaxed);
+        current_task.store(NO_TASK, Ordering::Relaxed);
+
+        match poll {
+            Poll::Ready(()) => {
+                // wakers of a finished task may outlive it, keep them
+                // from queuing it again
+                task_waker.scheduled.store(true, Ordering::Release);
+                // task done so drop it and remove it from the task list
+                *guard = None;
+                interrupts::without_interrupts(|| {
+                    self.shared.tasks.lock().remove(&task_id);
+                });
+            },
+            Poll::Pending => {},
         }
     }
 
+    /// Runs the executor forever.
+    ///
+    /// The first executor to run also becomes the target of `task::spawn`.
     pub fn run(&mut self) -> ! {
+        super::set_spawner(self.spawner());
+
         loop {
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
+    /// Halts until the next interrupt if there is nothing to run. Tasks
+    /// queued on other CPUs are stolen after the next timer interrupt.
     fn sleep_if_idle(&self) {
-        use x86_64::instructions::interrupts;
-
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if self.shared.ready_queues[self.cpu].is_empty()
+            && self.shared.new_tasks.is_empty()
+            && self.shared.overflow_tasks.lock().is_empty()
+        {
             interrupts::enable_and_hlt();
         } else {
             interrupts::enable();
@@ -90,16 +332,170 @@ impl Default for Executor {
     }
 }
 
+impl Drop for Executor {
+    fn drop(&mut self) {
+        // the task list and the wakers in it point to each other, the
+        // other executors still need them
+        if self.shared.executors.fetch_sub(1, Ordering::Relaxed) == 1 {
+            interrupts::without_interrupts(|| {
+                self.shared.tasks.lock().clear();
+            });
+        }
+    }
+}
+
+impl Spawner {
+    /// Queues `task` to be spawned by an executor on its next round.
+    ///
+    /// Allocates if `NEW_TASKS_CAPACITY` tasks are already waiting, so
+    /// interrupt handlers must use `try_spawn`.
+    pub fn spawn(&self, task: Task) {
+        interrupts::without_interrupts(|| {
+            let mut overflow = self.shared.overflow_tasks.lock();
+            // behind the tasks that did not fit already, to keep the order
+            if !overflow.is_empty() {
+                overflow.push_back(task);
+            } else if let Err(task) = self.try_spawn(task) {
+                overflow.push_back(task);
+            }
+        });
+    }
+
+    /// Queues `task` to be spawned by an executor on its next round, or
+    /// gives it back if `NEW_TASKS_CAPACITY` tasks are already waiting.
+    pub fn try_spawn(&self, task: Task) -> Result<(), Task> {
+        self.shared.new_tasks.push(task)
+    }
+
+    /// Lists the live tasks of the executor, see `Executor::tasks`.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.shared.tasks()
+    }
+}
+
+impl Shared {
+    fn tasks(&self) -> Vec<TaskInfo> {
+        let current_tasks: Vec<u64> = self.current_tasks.iter()
+            .map(|task| task.load(Ordering::Relaxed))
+            .filter(|&task| task != NO_TASK)
+            .collect();
+        interrupts::without_interrupts(|| {
+            self.tasks.lock()
+                .values()
+                .map(|task| task.info(&current_tasks))
+                .collect()
+        })
+    }
+}
+
+impl ReadyQueue {
+    fn new() -> Self {
+        const EMPTY: VecDeque<TaskId> = VecDeque::new();
+        Self {
+            queues: Mutex::new([EMPTY; Priority::COUNT]),
+        }
+    }
+
+    /// Makes room for `tasks` queued IDs in every priority class.
+    ///
+    /// May allocate, so it is only called by the executors when spawning.
+    fn reserve(&self, tasks: usize) {
+        interrupts::without_interrupts(|| {
+            for queue in self.queues.lock().iter_mut() {
+                let additional = tasks.saturating_sub(queue.len());
+                queue.reserve(additional);
+            }
+        });
+    }
+
+    fn push(&self, task_id: TaskId, priority: Priority) {
+        interrupts::without_interrupts(|| {
+            self.queues.lock()[priority.index()].push_back(task_id);
+        });
+    }
+
+    /// Pops a task from the highest priority class that is not empty and
+    /// still has some budget left, and charges it one poll.
+    fn pop(&self, budgets: &mut [usize; Priority::COUNT]) -> Option<TaskId> {
+        interrupts::without_interrupts(|| {
+            let mut queues = self.queues.lock();
+            queues.iter_mut()
+                .zip(budgets.iter_mut())
+                .filter(|(_, budget)| **budget > 0)
+                .find_map(|(queue, budget)| {
+                    let task_id = queue.pop_front()?;
+                    *budget -= 1;
+                    Some(task_id)
+                })
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        interrupts::without_interrupts(|| {
+            self.queues.lock().iter().all(VecDeque::is_empty)
+        })
+    }
+}
+
 impl TaskWaker {
-    fn new_waker(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(Self {
-            task_id,
-            task_queue,
-        }))
+    fn new(
+        task: Task,
+        priority: Priority,
+        name: Option<String>,
+        owner: usize,
+        shared: Arc<Shared>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            task_id: task.id,
+            priority,
+            name,
+            task: Mutex::new(Some(task)),
+            owner: AtomicUsize::new(owner),
+            scheduled: AtomicBool::new(true),
+            shared,
+            polls: AtomicU64::new(0),
+            poll_cycles: AtomicU64::new(0),
+            last_wake: AtomicU64::new(WOKEN_BY_SPAWN),
+        })
     } 
 
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect(&TASK_QUEUE_FULL_MESSAGE);
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            let current_task = self.shared.current_tasks[smp::cpu_index()]
+                .load(Ordering::Relaxed);
+            let source = match current_task {
+                NO_TASK => WOKEN_EXTERNALLY,
+                task_id => task_id,
+            };
+            self.last_wake.store(source, Ordering::Relaxed);
+            let owner = self.owner.load(Ordering::Acquire);
+            self.shared.ready_queues[owner].push(self.task_id, self.priority);
+        }
+    }
+
+    fn info(&self, current_tasks: &[u64]) -> TaskInfo {
+        let state = if current_tasks.contains(&self.task_id.0) {
+            TaskState::Running
+        } else if self.scheduled.load(Ordering::Acquire) {
+            TaskState::Queued
+        } else {
+            TaskState::Pending
+        };
+        let last_wake = match self.last_wake.load(Ordering::Relaxed) {
+            WOKEN_BY_SPAWN => WakeSource::Spawn,
+            WOKEN_EXTERNALLY => WakeSource::External,
+            task_id if task_id == self.task_id.0 => WakeSource::Itself,
+            task_id => WakeSource::Task(TaskId(task_id)),
+        };
+        TaskInfo {
+            id: self.task_id,
+            name: self.name.clone(),
+            priority: self.priority,
+            state,
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_cycles: self.poll_cycles.load(Ordering::Relaxed),
+            last_wake,
+        }
     }
 }
 
@@ -112,3 +508,210 @@ impl Wake for TaskWaker {
         self.wake_task();
     }
 }
+
+impl fmt::Display for TaskInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // derived `Debug` ignores the padding, hence the strings
+        write!(f, "{:>4} {:<16} {:<6} {:<7} polls: {:<6} cycles: {:<10} woken by: ",
+            self.id,
+            self.name.as_deref().unwrap_or("-"),
+            format!("{:?}", self.priority),
+            format!("{:?}", self.state),
+            self.polls,
+            self.poll_cycles)?;
+        match self.last_wake {
+            WakeSource::Spawn => write!(f, "spawn"),
+            WakeSource::Itself => write!(f, "itself"),
+            WakeSource::Task(task_id) => write!(f, "task {}", task_id),
+            WakeSource::External => write!(f, "interrupt"),
+        }
+    }
+}
+
+/// Reads the CPU timestamp counter, used to time polls.
+fn cycles() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[test_case]
+fn test_spawn_from_task() {
+    static CHILD_RAN: AtomicBool = AtomicBool::new(false);
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor.spawn(Task::new(async move {
+        spawner.spawn(Task::new(async {
+            CHILD_RAN.store(true, Ordering::Relaxed);
+        }));
+    }));
+    executor.run_ready_tasks();
+
+    assert!(CHILD_RAN.load(Ordering::Relaxed));
+    assert!(executor.tasks().is_empty());
+}
+
+#[test_case]
+fn test_spawn_more_than_queue_capacity() {
+    static CHILDREN_RAN: AtomicUsize = AtomicUsize::new(0);
+    const CHILDREN: usize = NEW_TASKS_CAPACITY * 2;
+
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    executor.spawn(Task::new(async move {
+        for _ in 0..CHILDREN {
+            spawner.spawn(Task::new(async {
+                CHILDREN_RAN.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+        // the queue is full, but `spawn` still takes them
+        assert!(spawner.try_spawn(Task::new(async {})).is_err());
+    }));
+    executor.run_ready_tasks();
+
+    assert_eq!(CHILDREN_RAN.load(Ordering::Relaxed), CHILDREN);
+    assert!(executor.tasks().is_empty());
+}
+
+#[test_case]
+fn test_duplicate_wakes_coalesced() {
+    use core::sync::atomic::AtomicUsize;
+    use futures_util::future::poll_fn;
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(poll_fn(|cx| {
+        if POLLS.fetch_add(1, Ordering::Relaxed) > 0 {
+            return Poll::Ready(());
+        }
+        // way more wakes than there are tasks
+        for _ in 0..1000 {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    })));
+    executor.run_ready_tasks();
+
+    assert_eq!(POLLS.load(Ordering::Relaxed), 2);
+    assert!(executor.shared.ready_queues[executor.cpu].is_empty());
+}
+
+/// A task that never completes and wakes itself on every poll
+#[cfg(test)]
+fn busy_task(polls: &'static core::sync::atomic::AtomicUsize) -> Task {
+    use futures_util::future::poll_fn;
+
+    Task::new(poll_fn(move |cx| {
+        polls.fetch_add(1, Ordering::Relaxed);
+        cx.waker().wake_by_ref();
+        Poll::<()>::Pending
+    }))
+}
+
+#[test_case]
+fn test_busy_task_does_not_starve_high_priority() {
+    use core::sync::atomic::AtomicUsize;
+    static BUSY_POLLS: AtomicUsize = AtomicUsize::new(0);
+    static BUSY_POLLS_BEFORE_HIGH: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    let mut executor = Executor::new();
+    executor.spawn(busy_task(&BUSY_POLLS));
+    executor.run_ready_tasks();
+
+    // spawned after the busy task, but polled before it
+    executor.spawn(Task::new(async {
+        let busy_polls = BUSY_POLLS.load(Ordering::Relaxed);
+        BUSY_POLLS_BEFORE_HIGH.store(busy_polls, Ordering::Relaxed);
+    }).with_priority(Priority::High));
+    let busy_polls = BUSY_POLLS.load(Ordering::Relaxed);
+    executor.run_ready_tasks();
+
+    assert_eq!(BUSY_POLLS_BEFORE_HIGH.load(Ordering::Relaxed), busy_polls);
+    assert_eq!(executor.tasks().len(), 1);
+}
+
+#[test_case]
+fn test_busy_high_priority_task_does_not_starve_low_priority() {
+    use core::sync::atomic::AtomicUsize;
+    static BUSY_POLLS: AtomicUsize = AtomicUsize::new(0);
+    static LOW_RAN: AtomicBool = AtomicBool::new(false);
+
+    let mut executor = Executor::new();
+    executor.spawn(busy_task(&BUSY_POLLS).with_priority(Priority::High));
+    executor.spawn(Task::new(async {
+        LOW_RAN.store(true, Ordering::Relaxed);
+    }).with_priority(Priority::Low));
+    executor.run_ready_tasks();
+
+    assert!(LOW_RAN.load(Ordering::Relaxed));
+    assert_eq!(
+        BUSY_POLLS.load(Ordering::Relaxed),
+        ROUND_BUDGETS[Priority::High.index()]
+    );
+}
+
+#[test_case]
+fn test_task_list() {
+    use super::sync::Notify;
+    static FIRST: Notify = Notify::new();
+    static SECOND: Notify = Notify::new();
+
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        FIRST.notified().await;
+        SECOND.notified().await;
+    }).with_name("waiter"));
+    executor.run_ready_tasks();
+
+    let tasks = executor.tasks();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].name.as_deref(), Some("waiter"));
+    assert_eq!(tasks[0].state, TaskState::Pending);
+    assert_eq!(tasks[0].polls, 1);
+    assert_eq!(tasks[0].last_wake, WakeSource::Spawn);
+
+    let notifier = Task::new(async { FIRST.notify_one() });
+    let notifier_id = notifier.id;
+    executor.spawn(notifier);
+    assert_eq!(executor.tasks()[1].state, TaskState::Queued);
+    executor.run_ready_tasks();
+
+    let tasks = executor.tasks();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].polls, 2);
+    assert_eq!(tasks[0].last_wake, WakeSource::Task(notifier_id));
+
+    SECOND.notify_one();
+    let tasks = executor.tasks();
+    assert_eq!(tasks[0].state, TaskState::Queued);
+    assert_eq!(tasks[0].last_wake, WakeSource::External);
+    executor.run_ready_tasks();
+    assert!(executor.tasks().is_empty());
+}
+
+#[test_case]
+fn test_idle_executor_steals_and_owns_tasks() {
+    use super::sync::Notify;
+    static NOTIFY: Notify = Notify::new();
+    static DONE: AtomicBool = AtomicBool::new(false);
+
+    // stands for the executor of a second CPU
+    let mut owner = Executor::new();
+    let mut thief = Executor::with_cpu(owner.shared.clone(), owner.cpu + 1);
+    owner.spawn(Task::new(async {
+        NOTIFY.notified().await;
+        DONE.store(true, Ordering::Relaxed);
+    }));
+
+    thief.run_ready_tasks();
+    let tasks = owner.tasks();
+    assert_eq!(tasks[0].polls, 1);
+    assert!(owner.shared.ready_queues[owner.cpu].is_empty());
+
+    // woken on the queue of the thief, which now owns it
+    NOTIFY.notify_one();
+    assert!(owner.shared.ready_queues[owner.cpu].is_empty());
+    assert!(!thief.shared.ready_queues[thief.cpu].is_empty());
+    thief.run_ready_tasks();
+    assert!(DONE.load(Ordering::Relaxed));
+    assert!(owner.tasks().is_empty());
+}
@@ -0,0 +1,99 @@
+//! Queue of the bytes read by the interrupt handler of an input device.
+//!
+//! The handler must not block, so when the queue is full, or before its
+//! reader was created, the byte is dropped and only counted. A key press
+//! while the executor is busy loses the key, instead of crashing the
+//! kernel.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+
+pub(crate) struct InputQueue {
+    queue: OnceCell<ArrayQueue<u8>>,
+    capacity: usize,
+    waker: AtomicWaker,
+    /// Bytes dropped because the queue was full or had no reader yet
+    dropped: AtomicU64,
+}
+
+impl InputQueue {
+    pub(crate) const fn new(capacity: usize) -> Self {
+        InputQueue {
+            queue: OnceCell::uninit(),
+            capacity,
+            waker: AtomicWaker::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Allocates the queue for its only reader.
+    ///
+    /// Panics if called more than once.
+    pub(crate) fn init(&self) {
+        self.queue.try_init_once(|| ArrayQueue::new(self.capacity))
+            .expect("an input queue should only have one reader");
+    }
+
+    /// Called by the interrupt handler
+    ///
+    /// Does not block or allocate.
+    pub(crate) fn push(&self, byte: u8) {
+        match self.queue.try_get() {
+            Ok(queue) if queue.push(byte).is_ok() => self.waker.wake(),
+            _ => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the next byte, or registers the waker of `cx` to be woken up
+    /// by the next `push`.
+    pub(crate) fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<u8> {
+        // should never panic as the reader calls `init` in its constructor
+        let queue = self.queue
+            .try_get()
+            .expect("input queue not initialized");
+
+        if let Some(byte) = queue.pop() {
+            return Poll::Ready(byte);
+        }
+
+        self.waker.register(cx.waker());
+        match queue.pop() {
+            Some(byte) => {
+                self.waker.take();
+                Poll::Ready(byte)
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    /// Number of bytes dropped since boot
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[test_case]
+fn test_full_queue_drops_and_counts() {
+    use futures_util::task::noop_waker_ref;
+
+    let input = InputQueue::new(2);
+    input.push(0);
+    assert_eq!(input.dropped(), 1);
+
+    input.init();
+    for byte in 1..=3 {
+        input.push(byte);
+    }
+    assert_eq!(input.dropped(), 2);
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert_eq!(input.poll_pop(&mut cx), Poll::Ready(1));
+    assert_eq!(input.poll_pop(&mut cx), Poll::Ready(2));
+    assert_eq!(input.poll_pop(&mut cx), Poll::Pending);
+}
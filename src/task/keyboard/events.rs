@@ -0,0 +1,326 @@
+//! Decoded key events, broadcast to any number of subscribers.
+//!
+//! `run` is the only reader of the scancodes: it decodes them once with the
+//! current `Layout`, and publishes a `KeyEvent` to the queue of every
+//! `KeyEventStream` returned by `subscribe`, or by `subscribe_terminal` for
+//! the ones only reading while a virtual terminal is shown. A subscriber that
+//! does not keep up loses the events that do not fit in its queue, see
+//! `KeyEventStream::lost`.
+//!
+//! Alt+F1 to Alt+F6 switch between the virtual terminals, and are not
+//! published.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+use futures_util::{Stream, StreamExt};
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, KeyState, Keyboard, ScancodeSet1};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::layout::CurrentLayout;
+use super::ScancodeStream;
+use crate::{ps2, vga_buffer};
+
+/// Number of events each subscriber can have waiting
+const SUBSCRIBER_QUEUE_CAPACITY: usize = 64;
+
+/// Keys switching to the virtual terminals with Alt, in order
+const TERMINAL_KEYS: [KeyCode; vga_buffer::TERMINAL_COUNT] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+];
+
+/// Queues of the live subscribers
+///
+/// Only locked with interrupts disabled.
+static SUBSCRIBERS: Mutex<Vec<Weak<Subscriber>>> = Mutex::new(Vec::new());
+
+/// A key going down or up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    /// `KeyState::Down` or `KeyState::Up`
+    pub state: KeyState,
+    /// Modifiers after this event was taken into account
+    pub modifiers: Modifiers,
+    /// The character typed, for key presses that type one
+    pub unicode: Option<char>,
+}
+
+/// State of the modifier keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub alt_gr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+/// Stream of the key events published after `subscribe` was called
+pub struct KeyEventStream {
+    subscriber: Arc<Subscriber>,
+}
+
+struct Subscriber {
+    /// Index of the virtual terminal it only reads while shown, if any
+    terminal: Option<usize>,
+    queue: ArrayQueue<KeyEvent>,
+    waker: AtomicWaker,
+    /// Events dropped because `queue` was full
+    lost: AtomicU64,
+}
+
+/// Returns a stream of the key events to come.
+pub fn subscribe() -> KeyEventStream {
+    new_subscriber(None)
+}
+
+/// Returns a stream of the key events to come while virtual terminal
+/// `index` is shown, its input.
+pub fn subscribe_terminal(index: usize) -> KeyEventStream {
+    new_subscriber(Some(index))
+}
+
+fn new_subscriber(terminal: Option<usize>) -> KeyEventStream {
+    let subscriber = Arc::new(Subscriber {
+        terminal,
+        queue: ArrayQueue::new(SUBSCRIBER_QUEUE_CAPACITY),
+        waker: AtomicWaker::new(),
+        lost: AtomicU64::new(0),
+    });
+    interrupts::without_interrupts(|| {
+        SUBSCRIBERS.lock().push(Arc::downgrade(&subscriber));
+    });
+    KeyEventStream { subscriber }
+}
+
+/// Sends `event` to every subscriber reading, and forgets the dropped ones.
+fn publish(event: KeyEvent) {
+    let active = vga_buffer::active_terminal();
+    interrupts::without_interrupts(|| {
+        SUBSCRIBERS.lock().retain(|subscriber| match subscriber.upgrade() {
+            Some(subscriber) => {
+                if subscriber.terminal.is_some_and(|terminal| terminal != active) {
+                    return true;
+                }
+                if subscriber.queue.push(event).is_err() {
+                    subscriber.lost.fetch_add(1, Ordering::Relaxed);
+                }
+                subscriber.waker.wake();
+                true
+            }
+            None => false,
+        });
+    });
+}
+
+/// Decodes the scancodes and publishes the key events, forever.
+///
+/// Must be spawned once, as the only reader of the scancodes.
+pub async fn run() {
+    let mut scancodes = ScancodeStream::default();
+    let mut keyboard = Keyboard::new(
+        ScancodeSet1::new(),
+        CurrentLayout,
+        HandleControl::Ignore);
+    let mut modifiers = Modifiers::default();
+
+    while let Some(scancode) = scancodes.next().await {
+        let key_event = match keyboard.add_byte(scancode) {
+            Ok(Some(key_event)) => key_event,
+            _ => continue,
+        };
+        let code = key_event.code;
+        let state = key_event.state;
+        let locks = modifiers.leds();
+        modifiers.update(code, state);
+        if modifiers.leds() != locks {
+            // fails without a PS/2 keyboard, the LEDs do not matter then
+            let _ = ps2::set_leds(modifiers.leds()).await;
+        }
+        if scroll_view(code, state, modifiers) || switch_terminal(code, state, modifiers) {
+            continue;
+        }
+        let unicode = match keyboard.process_keyevent(key_event) {
+            Some(DecodedKey::Unicode(char)) => Some(char),
+            _ => None,
+        };
+        publish(KeyEvent { code, state, modifiers, unicode });
+    }
+}
+
+/// Scrolls the VGA console on Shift+PageUp and Shift+PageDown, returns
+/// `true` if the key was used to.
+fn scroll_view(code: KeyCode, state: KeyState, modifiers: Modifiers) -> bool {
+    // half a screen at a time
+    const LINES: usize = 12;
+
+    if !modifiers.shift || !matches!(code, KeyCode::PageUp | KeyCode::PageDown) {
+        return false;
+    }
+    if state == KeyState::Down {
+        match code {
+            KeyCode::PageUp => vga_buffer::scroll_up(LINES),
+            _ => vga_buffer::scroll_down(LINES),
+        }
+    }
+    true
+}
+
+/// Shows virtual terminal n on Alt+Fn, returns `true` if the key was used
+/// to.
+fn switch_terminal(code: KeyCode, state: KeyState, modifiers: Modifiers) -> bool {
+    let Some(index) = TERMINAL_KEYS.iter().position(|&key| key == code) else {
+        return false;
+    };
+    if !modifiers.alt {
+        return false;
+    }
+    if state == KeyState::Down {
+        vga_buffer::switch_terminal(index);
+    }
+    true
+}
+
+impl KeyEvent {
+    /// Whether the key is a modifier or a lock, that only changes how the
+    /// other keys are read
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self.code,
+            KeyCode::LShift | KeyCode::RShift
+                | KeyCode::LControl | KeyCode::RControl
+                | KeyCode::LAlt | KeyCode::RAltGr
+                | KeyCode::LWin | KeyCode::RWin
+                | KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock
+        )
+    }
+}
+
+impl Default for Modifiers {
+    /// No key down and Num Lock on, like the decoder of `run` and the LEDs
+    /// set by `ps2::init`
+    fn default() -> Self {
+        Self {
+            shift: false,
+            ctrl: false,
+            alt: false,
+            alt_gr: false,
+            caps_lock: false,
+            num_lock: true,
+            scroll_lock: false,
+        }
+    }
+}
+
+impl Modifiers {
+    /// Takes the key `code` going to `state` into account.
+    fn update(&mut self, code: KeyCode, state: KeyState) {
+        let down = state == KeyState::Down;
+        match code {
+            KeyCode::LShift | KeyCode::RShift => self.shift = down,
+            KeyCode::LControl | KeyCode::RControl => self.ctrl = down,
+            KeyCode::LAlt => self.alt = down,
+            KeyCode::RAltGr => self.alt_gr = down,
+            // locks toggle on each press
+            KeyCode::CapsLock if down => self.caps_lock = !self.caps_lock,
+            KeyCode::NumpadLock if down => self.num_lock = !self.num_lock,
+            KeyCode::ScrollLock if down => self.scroll_lock = !self.scroll_lock,
+            _ => {}
+        }
+    }
+
+    /// The keyboard LEDs showing the locks
+    fn leds(&self) -> ps2::Leds {
+        ps2::Leds {
+            scroll_lock: self.scroll_lock,
+            num_lock: self.num_lock,
+            caps_lock: self.caps_lock,
+        }
+    }
+}
+
+impl KeyEventStream {
+    /// Number of events this subscriber missed because it did not keep up
+    pub fn lost(&self) -> u64 {
+        self.subscriber.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<KeyEvent>> {
+        let subscriber = &self.subscriber;
+        if let Some(event) = subscriber.queue.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        subscriber.waker.register(cx.waker());
+        match subscriber.queue.pop() {
+            Some(event) => {
+                subscriber.waker.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test_case]
+fn test_events_reach_every_subscriber() {
+    use futures_util::FutureExt;
+
+    let mut first = subscribe();
+    let mut second = subscribe();
+    let mut modifiers = Modifiers::default();
+    modifiers.update(KeyCode::LShift, KeyState::Down);
+    let event = KeyEvent {
+        code: KeyCode::A,
+        state: KeyState::Down,
+        modifiers,
+        unicode: Some('A'),
+    };
+    publish(event);
+
+    assert_eq!(first.next().now_or_never(), Some(Some(event)));
+    assert_eq!(second.next().now_or_never(), Some(Some(event)));
+    assert!(first.next().now_or_never().is_none());
+    assert!(event.modifiers.shift);
+    // the decoder starts with Num Lock on
+    assert!(event.modifiers.num_lock);
+    assert_eq!(event.modifiers.leds(), ps2::Leds::default());
+    assert!(!event.is_modifier());
+    assert!(KeyEvent { code: KeyCode::LShift, unicode: None, ..event }.is_modifier());
+}
+
+#[test_case]
+fn test_terminal_subscribers_read_while_shown() {
+    use futures_util::FutureExt;
+
+    let mut shown = subscribe_terminal(vga_buffer::active_terminal());
+    let mut hidden = subscribe_terminal(vga_buffer::active_terminal() + 1);
+    let event = KeyEvent {
+        code: KeyCode::A,
+        state: KeyState::Down,
+        modifiers: Modifiers::default(),
+        unicode: Some('a'),
+    };
+    publish(event);
+
+    assert_eq!(shown.next().now_or_never(), Some(Some(event)));
+    assert!(hidden.next().now_or_never().is_none());
+}
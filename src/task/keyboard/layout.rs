@@ -0,0 +1,114 @@
+//! Keyboard layouts that can be switched at runtime.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, KeyboardLayout, Modifiers};
+
+/// The pc-keyboard layouts the keyboard can be set to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Layout {
+    #[default]
+    Us104,
+    Uk105,
+    Azerty,
+    Dvorak104,
+    De105,
+    Jis109,
+}
+
+/// `Layout` used to decode the key presses, see `set_layout`
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+
+/// Layout that follows `set_layout`, for `pc_keyboard::Keyboard`
+pub(super) struct CurrentLayout;
+
+/// Sets the layout used to decode the next key presses.
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+/// Returns the layout used to decode the key presses.
+pub fn layout() -> Layout {
+    Layout::ALL[LAYOUT.load(Ordering::Relaxed) as usize]
+}
+
+impl Layout {
+    /// Every layout, in declaration order
+    pub const ALL: [Layout; 6] = [
+        Layout::Us104,
+        Layout::Uk105,
+        Layout::Azerty,
+        Layout::Dvorak104,
+        Layout::De105,
+        Layout::Jis109,
+    ];
+
+    /// Short lowercase name, e.g. for a shell command
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us104 => "us",
+            Layout::Uk105 => "uk",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak104 => "dvorak",
+            Layout::De105 => "de",
+            Layout::Jis109 => "jis",
+        }
+    }
+
+    /// The layout called `name`, see `Layout::name`.
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Layout::ALL.into_iter().find(|layout| layout.name() == name)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl KeyboardLayout for Layout {
+    fn map_keycode(
+        &self,
+        keycode: KeyCode,
+        modifiers: &Modifiers,
+        handle_ctrl: HandleControl,
+    ) -> DecodedKey {
+        match self {
+            Layout::Us104 => layouts::Us104Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Uk105 => layouts::Uk105Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Azerty => layouts::Azerty.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Dvorak104 => layouts::Dvorak104Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::De105 => layouts::De105Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Jis109 => layouts::Jis109Key.map_keycode(keycode, modifiers, handle_ctrl),
+        }
+    }
+}
+
+impl KeyboardLayout for CurrentLayout {
+    fn map_keycode(
+        &self,
+        keycode: KeyCode,
+        modifiers: &Modifiers,
+        handle_ctrl: HandleControl,
+    ) -> DecodedKey {
+        layout().map_keycode(keycode, modifiers, handle_ctrl)
+    }
+}
+
+#[test_case]
+fn test_switch_layout() {
+    use pc_keyboard::{KeyEvent, KeyState, Keyboard, ScancodeSet1};
+
+    let mut keyboard = Keyboard::new(ScancodeSet1::new(), CurrentLayout, HandleControl::Ignore);
+    let mut press_q = || keyboard.process_keyevent(KeyEvent::new(KeyCode::Q, KeyState::Down));
+
+    let previous = layout();
+    set_layout(Layout::Azerty);
+    assert_eq!(press_q(), Some(DecodedKey::Unicode('a')));
+    set_layout(Layout::Us104);
+    assert_eq!(press_q(), Some(DecodedKey::Unicode('q')));
+    set_layout(previous);
+}
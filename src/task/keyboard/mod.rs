@@ -0,0 +1,80 @@
+use core::{pin::Pin, task::{Context, Poll}};
+
+use crate::print;
+
+use futures_util::{Stream, StreamExt};
+
+use super::input::InputQueue;
+
+mod events;
+mod layout;
+
+pub use events::{run, subscribe, subscribe_terminal, KeyEvent, KeyEventStream, Modifiers};
+pub use layout::{layout, set_layout, Layout};
+pub use pc_keyboard::{KeyCode, KeyState};
+
+/// The capacity of the SCANCODE_QUEUE, scancodes that do not fit are
+/// dropped, see `dropped_scancodes`
+const SCANCODE_QUEUE_CAPACITY: usize = 100;
+
+static SCANCODE_QUEUE: InputQueue = InputQueue::new(SCANCODE_QUEUE_CAPACITY);
+
+/// Called by the keyboard interrup handler
+///
+/// Must not block or allocate to avoir deadlocks.
+pub(crate) fn add_scancode(scancode: u8) {
+    SCANCODE_QUEUE.push(scancode);
+}
+
+/// Number of scancodes lost since boot, because `run` did not keep up
+pub fn dropped_scancodes() -> u64 {
+    SCANCODE_QUEUE.dropped()
+}
+
+/// The scancodes received from the keyboard, only read by `run`
+pub struct ScancodeStream {
+    /// This field prevents the construction of the struct
+    /// from outside this module to enfore the use of `new()`
+    _private: (),
+}
+
+impl ScancodeStream {
+    fn new() -> Self {
+        SCANCODE_QUEUE.init();
+
+        ScancodeStream { _private: () }
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        SCANCODE_QUEUE.poll_pop(cx).map(Some)
+    }
+}
+
+/// Prints the keys pressed on the first virtual terminal, decoded with the
+/// layout set by `set_layout`.
+///
+/// Needs `run` to be spawned too.
+pub async fn print_keypresses() {
+    let mut events = subscribe_terminal(0);
+
+    while let Some(event) = events.next().await {
+        // a capital letter is printed as such, not as the shift before it
+        if event.state != KeyState::Down || event.is_modifier() {
+            continue;
+        }
+        match event.unicode {
+            Some(char) => print!("{char}"),
+            None => print!("{:?}", event.code),
+        }
+    }
+}
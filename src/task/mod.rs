@@ -1,25 +1,64 @@
-use core::{pin::Pin, future::Future, task::{Poll, Context}, sync::atomic::{AtomicU64, Ordering}};
-use alloc::boxed::Box;
+use core::{fmt, pin::Pin, future::Future, task::{Poll, Context}, sync::atomic::{AtomicU64, Ordering}};
+use alloc::{boxed::Box, string::String};
+use conquer_once::spin::OnceCell;
+
+use self::executor::Spawner;
 
 pub mod executor;
+mod input;
 pub mod keyboard;
+pub mod mouse;
+pub mod serial;
+pub mod sync;
+
+/// Spawner of the running executor, used by `spawn`
+static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
 
 pub struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    priority: Priority,
+    /// Shown in `Executor::tasks`, moved out when the task is spawned
+    name: Option<String>,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
+
+/// Scheduling class of a task.
+///
+/// Higher priorities are polled first, but every class gets a share of
+/// each executor round so that none of them can be starved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Self {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Self {
         Task {
             id: TaskId::new(),
+            priority: Priority::default(),
+            name: None,
             future: Box::pin(future),
         }
     }
 
+    /// Names the task, to recognize it in `Executor::tasks`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the scheduling class of the task (`Priority::Normal` by default).
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
@@ -31,3 +70,42 @@ impl TaskId {
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
 }
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Spawns a task on the running executor.
+///
+/// Can be called from inside a task, e.g. to start a child task.
+/// Panics if no executor has been started with `Executor::run` yet.
+pub fn spawn(task: Task) {
+    SPAWNER
+        .try_get()
+        .expect("task::spawn called before an executor was started")
+        .spawn(task);
+}
+
+/// Makes `spawner` the target of `spawn`, unless an executor was started
+/// first. Lets the other CPUs join an executor before it runs.
+pub fn set_spawner(spawner: Spawner) {
+    // fails if another executor was started first, which is fine
+    let _ = SPAWNER.try_init_once(|| spawner);
+}
+
+/// Spawner of the executor that `spawn` targets, if there is one yet
+pub fn spawner() -> Option<&'static Spawner> {
+    SPAWNER.try_get().ok()
+}
+
+impl Priority {
+    /// Number of priority classes
+    const COUNT: usize = 3;
+
+    /// Index of the class, from the highest priority to the lowest.
+    fn index(self) -> usize {
+        self as usize
+    }
+}
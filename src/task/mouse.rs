@@ -0,0 +1,183 @@
+//! PS/2 mouse, read as a stream of `MouseEvent`s.
+//!
+//! The mouse interrupt handler queues the bytes it reads, that
+//! `MouseStream` assembles into packets of 3 bytes, or 4 for the mice that
+//! `ps2::init` found a scroll wheel on.
+
+use core::{pin::Pin, task::{Context, Poll}};
+
+use crate::ps2::{self, Channel, DeviceType};
+
+use futures_util::{ready, Stream};
+
+use super::input::InputQueue;
+
+/// The capacity of the BYTE_QUEUE, about a second of movement. Bytes that
+/// do not fit are dropped, see `dropped_bytes`
+const BYTE_QUEUE_CAPACITY: usize = 400;
+
+static BYTE_QUEUE: InputQueue = InputQueue::new(BYTE_QUEUE_CAPACITY);
+
+/// Bits of the first byte of a packet
+const LEFT_BUTTON: u8 = 1 << 0;
+const RIGHT_BUTTON: u8 = 1 << 1;
+const MIDDLE_BUTTON: u8 = 1 << 2;
+/// Always set, used to find the start of the packets
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+/// Bits of the fourth byte of a packet, for five button mice
+const FOURTH_BUTTON: u8 = 1 << 4;
+const FIFTH_BUTTON: u8 = 1 << 5;
+
+/// A movement of the mouse, with the buttons held at that time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseEvent {
+    /// Horizontal movement, positive to the right
+    pub dx: i16,
+    /// Vertical movement, positive downwards as on the screen
+    pub dy: i16,
+    /// Scroll wheel movement, positive towards the user
+    pub dz: i8,
+    pub buttons: Buttons,
+}
+
+/// Buttons held down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Buttons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    pub fourth: bool,
+    pub fifth: bool,
+}
+
+/// Called by the mouse interrupt handler
+///
+/// Must not block or allocate to avoid deadlocks.
+pub(crate) fn add_byte(byte: u8) {
+    BYTE_QUEUE.push(byte);
+}
+
+/// Number of bytes lost since boot, because no `MouseStream` kept up. The
+/// stream skips the rest of a packet missing a byte.
+pub fn dropped_bytes() -> u64 {
+    BYTE_QUEUE.dropped()
+}
+
+/// The events of the mouse
+pub struct MouseStream {
+    device: DeviceType,
+    packet: [u8; 4],
+    /// Number of bytes of `packet` received
+    received: usize,
+}
+
+impl MouseStream {
+    /// Panics if there is no mouse, or if called more than once.
+    pub fn new() -> Self {
+        let device = ps2::device(Channel::Second)
+            .filter(|device| device.is_mouse())
+            .expect("no PS/2 mouse");
+        BYTE_QUEUE.init();
+
+        MouseStream { device, packet: [0; 4], received: 0 }
+    }
+
+    fn packet_size(&self) -> usize {
+        match self.device {
+            DeviceType::ScrollMouse | DeviceType::FiveButtonMouse => 4,
+            _ => 3,
+        }
+    }
+
+    /// Adds `byte` to the packet, and returns the packet's event once it
+    /// is complete.
+    fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.received == 0 && byte & ALWAYS_ONE == 0 {
+            // not the start of a packet, lost in the middle of one
+            return None;
+        }
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_size() {
+            return None;
+        }
+        self.received = 0;
+        Some(MouseEvent::from_packet(self.device, &self.packet))
+    }
+}
+
+impl Default for MouseStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let byte = ready!(BYTE_QUEUE.poll_pop(cx));
+            if let Some(event) = self.add_byte(byte) {
+                return Poll::Ready(Some(event));
+            }
+        }
+    }
+}
+
+impl MouseEvent {
+    fn from_packet(device: DeviceType, packet: &[u8; 4]) -> Self {
+        let flags = packet[0];
+        let axis = |byte: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                // the movement is meaningless
+                0
+            } else if flags & sign != 0 {
+                byte as i16 - 0x100
+            } else {
+                byte as i16
+            }
+        };
+        let mut event = MouseEvent {
+            dx: axis(packet[1], X_SIGN, X_OVERFLOW),
+            dy: -axis(packet[2], Y_SIGN, Y_OVERFLOW),
+            dz: 0,
+            buttons: Buttons {
+                left: flags & LEFT_BUTTON != 0,
+                right: flags & RIGHT_BUTTON != 0,
+                middle: flags & MIDDLE_BUTTON != 0,
+                fourth: false,
+                fifth: false,
+            },
+        };
+        match device {
+            DeviceType::ScrollMouse => event.dz = packet[3] as i8,
+            DeviceType::FiveButtonMouse => {
+                // sign extends the low 4 bits
+                event.dz = ((packet[3] << 4) as i8) >> 4;
+                event.buttons.fourth = packet[3] & FOURTH_BUTTON != 0;
+                event.buttons.fifth = packet[3] & FIFTH_BUTTON != 0;
+            }
+            _ => {}
+        }
+        event
+    }
+}
+
+#[test_case]
+fn test_parse_packets() {
+    let packet = [ALWAYS_ONE | LEFT_BUTTON | Y_SIGN, 5, 0xfe, 0];
+    let event = MouseEvent::from_packet(DeviceType::Mouse, &packet);
+    assert_eq!((event.dx, event.dy, event.dz), (5, 2, 0));
+    assert!(event.buttons.left && !event.buttons.right);
+
+    let packet = [ALWAYS_ONE | X_SIGN | X_OVERFLOW, 0x80, 3, 0x1f];
+    let event = MouseEvent::from_packet(DeviceType::FiveButtonMouse, &packet);
+    assert_eq!((event.dx, event.dy, event.dz), (0, -3, -1));
+    assert!(event.buttons.fourth && !event.buttons.fifth);
+}
@@ -0,0 +1,83 @@
+//! Bytes received on the serial ports.
+//!
+//! The interrupt handler of a port adds the bytes it reads to the queue of
+//! the port, that its `SerialStream` reads.
+
+use core::{pin::Pin, task::{Context, Poll}};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::serial::{Com, SerialPort};
+
+use super::input::InputQueue;
+
+/// The capacity of each queue, a few lines pasted at once. Bytes that do
+/// not fit are dropped, see `dropped_bytes`
+const QUEUE_CAPACITY: usize = 256;
+
+static QUEUES: [InputQueue; 4] = [const { InputQueue::new(QUEUE_CAPACITY) }; 4];
+
+/// Called by the serial interrupt handler
+///
+/// Must not block or allocate to avoid deadlocks.
+pub(crate) fn add_byte(com: Com, byte: u8) {
+    QUEUES[com as usize].push(byte);
+}
+
+/// Number of bytes received on `com` and lost since boot, because no
+/// `SerialStream` kept up.
+pub fn dropped_bytes(com: Com) -> u64 {
+    QUEUES[com as usize].dropped()
+}
+
+/// The bytes received on a serial port
+pub struct SerialStream {
+    com: Com,
+}
+
+impl SerialStream {
+    /// Panics if called more than once for the same port.
+    pub fn new(com: Com) -> Self {
+        QUEUES[com as usize].init();
+        SerialStream { com }
+    }
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u8>> {
+        QUEUES[self.com as usize].poll_pop(cx).map(Some)
+    }
+}
+
+/// Sends back what is received on `port`, a carriage return as a new line,
+/// so that a terminal on the other end shows what is typed.
+pub async fn echo(port: SerialPort) {
+    let mut bytes = SerialStream::new(port.com());
+    while let Some(byte) = bytes.next().await {
+        match byte {
+            b'\r' => port.write(b"\r\n"),
+            // backspace and delete
+            0x08 | 0x7f => port.write(b"\x08 \x08"),
+            _ => port.write(&[byte]),
+        }
+    }
+}
+
+#[test_case]
+fn test_received_bytes_are_streamed() {
+    use futures_util::FutureExt;
+
+    // nothing is connected to COM4, whatever the machine
+    let com = Com::Com4;
+    add_byte(com, b'!');
+    assert_eq!(dropped_bytes(com), 1);
+
+    let mut bytes = SerialStream::new(com);
+    add_byte(com, b'o');
+    add_byte(com, b'k');
+    assert_eq!(bytes.next().now_or_never(), Some(Some(b'o')));
+    assert_eq!(bytes.next().now_or_never(), Some(Some(b'k')));
+    assert_eq!(bytes.next().now_or_never(), None);
+}
@@ -0,0 +1,19 @@
+//! Async synchronization primitives for tasks.
+//!
+//! Unlike `spin::Mutex`, waiting on these suspends the current task instead
+//! of spinning, so they can be held across `.await` points without blocking
+//! the executor. Their internal locks are always taken with interrupts
+//! disabled, and the operations documented as such never allocate, so they
+//! can be used to signal tasks from interrupt handlers.
+
+pub mod mpsc;
+pub mod oneshot;
+mod mutex;
+mod notify;
+mod rwlock;
+mod semaphore;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notify, Notified};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
@@ -0,0 +1,280 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{Stream, StreamExt, task::AtomicWaker};
+use x86_64::instructions::interrupts;
+
+/// Sending half of a bounded multi-producer, single-consumer channel.
+///
+/// `try_send` never blocks nor allocates, so it can be used from
+/// interrupt handlers. Tasks should prefer `send`, which waits for room.
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+/// Receiving half of a channel, also usable as a `Stream`.
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full, the value is given back
+    Full(T),
+    /// The receiver is gone, the value is given back
+    Closed(T),
+}
+
+/// The receiver is gone, the value is given back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Future returned by `Sender::send`.
+#[must_use = "futures do nothing unless polled"]
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    /// `None` once sent or given back
+    value: Option<T>,
+    /// Our place in the blocked senders, once we had to wait
+    waiter: Option<Arc<Waiter>>,
+}
+
+/// A sender waiting for room in the queue
+struct Waiter {
+    /// Set once the waiter was taken out of the queue to be woken up
+    woken: AtomicBool,
+    waker: AtomicWaker,
+}
+
+struct Chan<T> {
+    queue: ArrayQueue<T>,
+    senders: AtomicUsize,
+    receiver_dropped: AtomicBool,
+    receiver_waker: AtomicWaker,
+    /// Senders waiting for room in the queue, woken one per slot freed
+    ///
+    /// Registering and checking for room happen under this lock, so the
+    /// receiver can not free a slot in between without waking the sender.
+    blocked_senders: spin::Mutex<VecDeque<Arc<Waiter>>>,
+}
+
+/// Creates a channel holding at most `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan {
+        queue: ArrayQueue::new(capacity),
+        senders: AtomicUsize::new(1),
+        receiver_dropped: AtomicBool::new(false),
+        receiver_waker: AtomicWaker::new(),
+        blocked_senders: spin::Mutex::new(VecDeque::new()),
+    });
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.chan.receiver_dropped.load(Ordering::Acquire) {
+            return Err(TrySendError::Closed(value));
+        }
+        self.chan.queue.push(value).map_err(TrySendError::Full)?;
+        self.chan.receiver_waker.wake();
+        Ok(())
+    }
+
+    /// Sends `value`, waiting for room in the channel if it is full.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture { sender: self, value: Some(value), waiter: None }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.receiver_dropped.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.senders.fetch_add(1, Ordering::Relaxed);
+        Self { chan: self.chan.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.chan.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // let the receiver see the end of the stream
+            self.chan.receiver_waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, or `None` once every sender is gone
+    /// and the channel is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.next().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        let value = self.chan.queue.pop()?;
+        self.chan.wake_blocked_sender();
+        Some(value)
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(value) = self.try_recv() {
+            return Poll::Ready(Some(value));
+        }
+
+        self.chan.receiver_waker.register(cx.waker());
+        match self.try_recv() {
+            Some(value) => {
+                self.chan.receiver_waker.take();
+                Poll::Ready(Some(value))
+            }
+            None if self.chan.senders.load(Ordering::Acquire) == 0 => {
+                // a sender may have pushed right before leaving
+                Poll::Ready(self.try_recv())
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.chan.receiver_dropped.store(true, Ordering::Release);
+        interrupts::without_interrupts(|| {
+            for waiter in self.chan.blocked_senders.lock().drain(..) {
+                waiter.woken.store(true, Ordering::Release);
+                waiter.waker.wake();
+            }
+        });
+    }
+}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let chan = &this.sender.chan;
+        interrupts::without_interrupts(|| {
+            let mut blocked_senders = chan.blocked_senders.lock();
+            let unsent = this.value.take().expect("send polled after completion");
+            let result = match this.sender.try_send(unsent) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Closed(unsent)) => Err(SendError(unsent)),
+                Err(TrySendError::Full(unsent)) => {
+                    this.value = Some(unsent);
+                    let waiter = this.waiter.get_or_insert_with(|| Arc::new(Waiter {
+                        // not queued yet
+                        woken: AtomicBool::new(true),
+                        waker: AtomicWaker::new(),
+                    }));
+                    waiter.waker.register(cx.waker());
+                    // woken but beaten to the slot, wait again
+                    if waiter.woken.swap(false, Ordering::AcqRel) {
+                        blocked_senders.push_back(waiter.clone());
+                    }
+                    return Poll::Pending;
+                }
+            };
+            if let Some(waiter) = this.waiter.take() {
+                if !waiter.woken.load(Ordering::Acquire) {
+                    blocked_senders.retain(|other| !Arc::ptr_eq(other, &waiter));
+                }
+            }
+            Poll::Ready(result)
+        })
+    }
+}
+
+// the value is moved out, never pinned
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return,
+        };
+        let chan = &self.sender.chan;
+        let woken = interrupts::without_interrupts(|| {
+            let mut blocked_senders = chan.blocked_senders.lock();
+            if waiter.woken.load(Ordering::Acquire) {
+                return true;
+            }
+            blocked_senders.retain(|other| !Arc::ptr_eq(other, &waiter));
+            false
+        });
+        if woken {
+            // the slot we were woken for goes to the next sender
+            chan.wake_blocked_sender();
+        }
+    }
+}
+
+impl<T> Chan<T> {
+    fn wake_blocked_sender(&self) {
+        interrupts::without_interrupts(|| {
+            if let Some(waiter) = self.blocked_senders.lock().pop_front() {
+                waiter.woken.store(true, Ordering::Release);
+                waiter.waker.wake();
+            }
+        });
+    }
+}
+
+#[test_case]
+fn test_mpsc_send_waits_for_room() {
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let (sender, mut receiver) = channel(1);
+
+    sender.try_send(1).unwrap();
+    assert_eq!(sender.try_send(2), Err(TrySendError::Full(2)));
+    let mut send = sender.send(2).boxed();
+    assert!(send.poll_unpin(&mut cx).is_pending());
+
+    assert_eq!(receiver.try_recv(), Some(1));
+    assert!(send.poll_unpin(&mut cx).is_ready());
+    drop(send);
+    drop(sender);
+
+    assert_eq!(receiver.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+    assert_eq!(receiver.poll_next_unpin(&mut cx), Poll::Ready(None));
+}
+
+#[test_case]
+fn test_mpsc_dropped_send_passes_the_slot_on() {
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let (sender, mut receiver) = channel(1);
+
+    sender.try_send(0).unwrap();
+    let mut first = sender.send(1);
+    let mut second = sender.send(2);
+    // polling again must not queue the sender twice
+    for _ in 0..3 {
+        assert!(first.poll_unpin(&mut cx).is_pending());
+        assert!(second.poll_unpin(&mut cx).is_pending());
+    }
+    assert_eq!(receiver.chan.blocked_senders.lock().len(), 2);
+
+    // the first sender is woken for the slot, but gives up
+    assert_eq!(receiver.try_recv(), Some(0));
+    drop(first);
+    assert!(receiver.chan.blocked_senders.lock().is_empty());
+    assert!(second.poll_unpin(&mut cx).is_ready());
+    drop(second);
+    assert_eq!(receiver.try_recv(), Some(2));
+}
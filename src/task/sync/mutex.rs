@@ -0,0 +1,101 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use super::semaphore::{Semaphore, SemaphorePermit};
+
+/// An async mutex, that can be held across `.await` points.
+///
+/// Tasks waiting for the lock are suspended instead of spinning, so the
+/// executor keeps running other tasks meanwhile. The lock is handed over
+/// in FIFO order.
+pub struct Mutex<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+/// Gives access to the value of a locked `Mutex`, unlocks it when dropped.
+pub struct MutexGuard<'a, T: ?Sized> {
+    _permit: SemaphorePermit<'a>,
+    value: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(1),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        let permit = self.semaphore.acquire(1).await;
+        self.guard(permit)
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire(1)?;
+        Some(self.guard(permit))
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn guard<'a>(&'a self, permit: SemaphorePermit<'a>) -> MutexGuard<'a, T> {
+        MutexGuard {
+            _permit: permit,
+            // safe because the single permit gives exclusive access
+            value: unsafe { &mut *self.value.get() },
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+#[test_case]
+fn test_mutex_lock_handed_over_on_unlock() {
+    use core::task::Context;
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let mutex = Mutex::new(0);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let mut guard = mutex.try_lock().unwrap();
+    let mut waiting = mutex.lock().boxed();
+    assert!(waiting.poll_unpin(&mut cx).is_pending());
+    assert!(mutex.try_lock().is_none());
+
+    *guard += 1;
+    drop(guard);
+    match waiting.poll_unpin(&mut cx) {
+        core::task::Poll::Ready(guard) => assert_eq!(*guard, 1),
+        core::task::Poll::Pending => panic!("lock not handed over"),
+    };
+}
@@ -0,0 +1,178 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
+
+/// Notifies tasks of an event.
+///
+/// `notify_one` stores a permit when nobody is waiting, so a notification
+/// sent right before a task starts waiting is not lost. Notifying never
+/// allocates, so it can be done from interrupt handlers.
+pub struct Notify {
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    /// Set by `notify_one` when no task was waiting
+    permit: bool,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+/// Values of `Waiter::notified`
+const NOT_NOTIFIED: u8 = 0;
+const NOTIFIED_ONE: u8 = 1;
+const NOTIFIED_ALL: u8 = 2;
+
+struct Waiter {
+    notified: AtomicU8,
+    waker: AtomicWaker,
+}
+
+/// Future returned by `Notify::notified`.
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    waiter: Option<Arc<Waiter>>,
+    done: bool,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                permit: false,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Waits for a notification.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            waiter: None,
+            done: false,
+        }
+    }
+
+    /// Wakes the longest waiting task, or lets the next call to
+    /// `notified` complete immediately if no task is waiting.
+    pub fn notify_one(&self) {
+        interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+            match state.waiters.pop_front() {
+                Some(waiter) => waiter.notify(NOTIFIED_ONE),
+                None => state.permit = true,
+            }
+        });
+    }
+
+    /// Wakes all the tasks currently waiting. Does not store a permit.
+    pub fn notify_waiters(&self) {
+        interrupts::without_interrupts(|| {
+            for waiter in self.state.lock().waiters.drain(..) {
+                waiter.notify(NOTIFIED_ALL);
+            }
+        });
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Waiter {
+    fn notify(&self, how: u8) {
+        self.notified.store(how, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.done {
+            return Poll::Ready(());
+        }
+
+        if let Some(waiter) = &self.waiter {
+            waiter.waker.register(cx.waker());
+            if waiter.notified.load(Ordering::Acquire) == NOT_NOTIFIED {
+                return Poll::Pending;
+            }
+            self.done = true;
+            return Poll::Ready(());
+        }
+
+        let notify = self.notify;
+        let waiter = interrupts::without_interrupts(|| {
+            let mut state = notify.state.lock();
+            if state.permit {
+                state.permit = false;
+                return None;
+            }
+            let waiter = Arc::new(Waiter {
+                notified: AtomicU8::new(NOT_NOTIFIED),
+                waker: AtomicWaker::new(),
+            });
+            waiter.waker.register(cx.waker());
+            state.waiters.push_back(waiter.clone());
+            Some(waiter)
+        });
+
+        match waiter {
+            None => {
+                self.done = true;
+                Poll::Ready(())
+            }
+            Some(waiter) => {
+                self.waiter = Some(waiter);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return,
+        };
+        let forward = interrupts::without_interrupts(|| {
+            let mut state = self.notify.state.lock();
+            state.waiters.retain(|other| !Arc::ptr_eq(other, &waiter));
+            !self.done && waiter.notified.load(Ordering::Acquire) == NOTIFIED_ONE
+        });
+        // a `notify_one` was meant for someone that is actually waiting
+        if forward {
+            self.notify.notify_one();
+        }
+    }
+}
+
+#[test_case]
+fn test_notify_one_stores_permit() {
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let notify = Notify::new();
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    notify.notify_one();
+    assert!(notify.notified().poll_unpin(&mut cx).is_ready());
+
+    let mut first = notify.notified();
+    let mut second = notify.notified();
+    assert!(first.poll_unpin(&mut cx).is_pending());
+    assert!(second.poll_unpin(&mut cx).is_pending());
+    notify.notify_one();
+    // dropping the notified waiter passes the notification on
+    drop(first);
+    assert!(second.poll_unpin(&mut cx).is_ready());
+}
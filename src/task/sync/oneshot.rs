@@ -0,0 +1,118 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::sync::Arc;
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
+
+/// Sends a single value to a `Receiver`.
+///
+/// Sending never blocks nor allocates, so it can be done from an
+/// interrupt handler.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// Future resolving to the value sent through the matching `Sender`.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The `Sender` was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+struct Inner<T> {
+    value: spin::Mutex<Option<T>>,
+    /// Set once the sender is gone, whether it sent a value or not
+    sender_done: AtomicBool,
+    receiver_dropped: AtomicBool,
+    receiver_waker: AtomicWaker,
+}
+
+/// Creates a channel carrying a single value.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        value: spin::Mutex::new(None),
+        sender_done: AtomicBool::new(false),
+        receiver_dropped: AtomicBool::new(false),
+        receiver_waker: AtomicWaker::new(),
+    });
+    (Sender { inner: inner.clone() }, Receiver { inner })
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, or gives it back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.inner.receiver_dropped.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        interrupts::without_interrupts(|| {
+            *self.inner.value.lock() = Some(value);
+        });
+        // the receiver is woken when `self` is dropped
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.receiver_dropped.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.sender_done.store(true, Ordering::Release);
+        self.inner.receiver_waker.wake();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Takes the value if it has been sent already.
+    pub fn try_recv(&mut self) -> Option<T> {
+        if !self.inner.sender_done.load(Ordering::Acquire) {
+            return None;
+        }
+        interrupts::without_interrupts(|| self.inner.value.lock().take())
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &self.inner;
+        if !inner.sender_done.load(Ordering::Acquire) {
+            inner.receiver_waker.register(cx.waker());
+            // the sender may have finished before we registered
+            if !inner.sender_done.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+        }
+        let value = interrupts::without_interrupts(|| inner.value.lock().take());
+        Poll::Ready(value.ok_or(RecvError))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_dropped.store(true, Ordering::Release);
+    }
+}
+
+#[test_case]
+fn test_oneshot_send_and_drop() {
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let (sender, mut receiver) = channel();
+    assert!(receiver.poll_unpin(&mut cx).is_pending());
+    sender.send(42).unwrap();
+    assert_eq!(receiver.poll_unpin(&mut cx), Poll::Ready(Ok(42)));
+
+    let (sender, mut receiver) = channel::<()>();
+    drop(sender);
+    assert_eq!(receiver.poll_unpin(&mut cx), Poll::Ready(Err(RecvError)));
+}
@@ -0,0 +1,186 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use super::semaphore::{Semaphore, SemaphorePermit};
+
+/// Maximum number of concurrent readers of a `RwLock`
+///
+/// A writer takes all of the permits at once.
+const MAX_READERS: usize = usize::MAX >> 3;
+
+/// An async reader-writer lock, that can be held across `.await` points.
+///
+/// Requests are served in FIFO order, so a waiting writer blocks the
+/// readers that come after it and can not be starved by them.
+pub struct RwLock<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+/// Shared access to the value of a `RwLock`, released when dropped.
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    _permit: SemaphorePermit<'a>,
+    value: &'a T,
+}
+
+/// Exclusive access to the value of a `RwLock`, released when dropped.
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    _permit: SemaphorePermit<'a>,
+    value: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(MAX_READERS),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        let permit = self.semaphore.acquire(1).await;
+        self.read_guard(permit)
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let permit = self.semaphore.acquire(MAX_READERS).await;
+        self.write_guard(permit)
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire(1)?;
+        Some(self.read_guard(permit))
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire(MAX_READERS)?;
+        Some(self.write_guard(permit))
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn read_guard<'a>(&'a self, permit: SemaphorePermit<'a>) -> RwLockReadGuard<'a, T> {
+        RwLockReadGuard {
+            _permit: permit,
+            // safe because no writer can hold the lock with us
+            value: unsafe { &*self.value.get() },
+        }
+    }
+
+    fn write_guard<'a>(&'a self, permit: SemaphorePermit<'a>) -> RwLockWriteGuard<'a, T> {
+        RwLockWriteGuard {
+            _permit: permit,
+            // safe because a writer holds all the permits
+            value: unsafe { &mut *self.value.get() },
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+#[test_case]
+fn test_rwlock_shared_by_readers() {
+    use futures_util::FutureExt;
+
+    let lock = RwLock::new(1);
+    let first = lock.try_read().unwrap();
+    let second = lock.read().now_or_never().expect("second reader blocked");
+    assert_eq!(*first + *second, 2);
+    assert!(lock.try_write().is_none());
+}
+
+#[test_case]
+fn test_rwlock_writer_is_exclusive() {
+    use core::task::Context;
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let lock = RwLock::new(0);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let mut writer = lock.try_write().unwrap();
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_write().is_none());
+    let mut reader = lock.read().boxed();
+    let mut other_writer = lock.write().boxed();
+    assert!(reader.poll_unpin(&mut cx).is_pending());
+    assert!(other_writer.poll_unpin(&mut cx).is_pending());
+
+    *writer += 1;
+    drop(writer);
+    // the reader came first, the other writer waits for it
+    let reader = match reader.poll_unpin(&mut cx) {
+        core::task::Poll::Ready(reader) => reader,
+        core::task::Poll::Pending => panic!("lock not handed over to the reader"),
+    };
+    assert_eq!(*reader, 1);
+    assert!(other_writer.poll_unpin(&mut cx).is_pending());
+    drop(reader);
+    assert!(other_writer.poll_unpin(&mut cx).is_ready());
+}
+
+#[test_case]
+fn test_rwlock_waiting_writer_not_starved() {
+    use core::task::Context;
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let lock = RwLock::new(0);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let reader = lock.try_read().unwrap();
+    let mut writer = lock.write().boxed();
+    assert!(writer.poll_unpin(&mut cx).is_pending());
+    // readers coming after the writer wait behind it
+    assert!(lock.try_read().is_none());
+    let mut late_reader = lock.read().boxed();
+    assert!(late_reader.poll_unpin(&mut cx).is_pending());
+
+    drop(reader);
+    let mut writer = match writer.poll_unpin(&mut cx) {
+        core::task::Poll::Ready(writer) => writer,
+        core::task::Poll::Pending => panic!("writer starved by a late reader"),
+    };
+    assert!(late_reader.poll_unpin(&mut cx).is_pending());
+    *writer = 2;
+    drop(writer);
+    match late_reader.poll_unpin(&mut cx) {
+        core::task::Poll::Ready(reader) => assert_eq!(*reader, 2),
+        core::task::Poll::Pending => panic!("lock not handed over to the reader"),
+    };
+}
@@ -0,0 +1,210 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
+
+/// An async counting semaphore.
+///
+/// Waiters are served in FIFO order, so a task asking for many permits
+/// is not starved by tasks asking for fewer. Releasing permits never
+/// allocates, so `add_permits` can be called from interrupt handlers.
+pub struct Semaphore {
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+struct Waiter {
+    needed: usize,
+    /// Set once the permits have been handed over to the waiter
+    granted: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Future returned by `Semaphore::acquire`.
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    needed: usize,
+    /// Our place in the waiters queue, once we had to wait
+    waiter: Option<Arc<Waiter>>,
+}
+
+/// Permits acquired from a `Semaphore`, released when dropped.
+#[must_use = "the permits are released as soon as the permit is dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        interrupts::without_interrupts(|| self.state.lock().permits)
+    }
+
+    /// Waits until `permits` permits are available and takes them.
+    pub fn acquire(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            needed: permits,
+            waiter: None,
+        }
+    }
+
+    /// Takes `permits` permits if they are available and nobody is
+    /// waiting for them already.
+    pub fn try_acquire(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+        interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+            if state.waiters.is_empty() && state.permits >= permits {
+                state.permits -= permits;
+                Some(SemaphorePermit { semaphore: self, permits })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Adds `permits` permits, waking the waiters that can now proceed.
+    pub fn add_permits(&self, permits: usize) {
+        interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+            state.permits += permits;
+            state.grant_waiters();
+        });
+    }
+}
+
+impl State {
+    /// Hands the available permits over to the waiters, in order.
+    fn grant_waiters(&mut self) {
+        while let Some(waiter) = self.waiters.front() {
+            if waiter.needed > self.permits {
+                break;
+            }
+            self.permits -= waiter.needed;
+            waiter.granted.store(true, Ordering::Release);
+            waiter.waker.wake();
+            self.waiters.pop_front();
+        }
+    }
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        let needed = self.needed;
+
+        if let Some(waiter) = &self.waiter {
+            waiter.waker.register(cx.waker());
+            if !waiter.granted.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+            self.waiter = None;
+            return Poll::Ready(SemaphorePermit { semaphore, permits: needed });
+        }
+
+        let waiter = interrupts::without_interrupts(|| {
+            let mut state = semaphore.state.lock();
+            if state.waiters.is_empty() && state.permits >= needed {
+                state.permits -= needed;
+                return None;
+            }
+            let waiter = Arc::new(Waiter {
+                needed,
+                granted: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            });
+            waiter.waker.register(cx.waker());
+            state.waiters.push_back(waiter.clone());
+            Some(waiter)
+        });
+
+        match waiter {
+            None => Poll::Ready(SemaphorePermit { semaphore, permits: needed }),
+            Some(waiter) => {
+                self.waiter = Some(waiter);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return,
+        };
+        interrupts::without_interrupts(|| {
+            let mut state = self.semaphore.state.lock();
+            if waiter.granted.load(Ordering::Acquire) {
+                // granted but never polled again, give the permits back
+                state.permits += waiter.needed;
+            } else {
+                state.waiters.retain(|other| !Arc::ptr_eq(other, &waiter));
+            }
+            // we may have been the one blocking the head of the queue
+            state.grant_waiters();
+        });
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permits taken instead of releasing them on drop.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+#[test_case]
+fn test_semaphore_waiters_served_in_order() {
+    use futures_util::{FutureExt, task::noop_waker_ref};
+
+    let semaphore = Semaphore::new(2);
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let permit = semaphore.try_acquire(2).unwrap();
+    let mut big = semaphore.acquire(2);
+    let mut small = semaphore.acquire(1);
+    assert!(big.poll_unpin(&mut cx).is_pending());
+    assert!(small.poll_unpin(&mut cx).is_pending());
+
+    // not enough for the first waiter, so the second one must wait too
+    semaphore.add_permits(1);
+    assert!(small.poll_unpin(&mut cx).is_pending());
+
+    drop(permit);
+    let big_permit = match big.poll_unpin(&mut cx) {
+        Poll::Ready(permit) => permit,
+        Poll::Pending => panic!("first waiter not granted"),
+    };
+    assert!(small.poll_unpin(&mut cx).is_ready());
+    drop(big_permit);
+    assert_eq!(semaphore.available_permits(), 3);
+}
@@ -0,0 +1,130 @@
+//! Interrupt entries that can switch threads.
+//!
+//! They save the general purpose registers of the interrupted thread on its
+//! stack, next to the interrupt stack frame, and hand the resulting stack
+//! pointer to a Rust handler. The handler returns the stack pointer of the
+//! thread to resume, whose registers are restored the same way before
+//! `iretq` jumps back into it.
+
+use core::arch::global_asm;
+
+use x86_64::VirtAddr;
+use x86_64::registers::segmentation::{CS, SS, Segment};
+
+global_asm!(
+    ".macro SWITCHING_ENTRY name, handler",
+    ".global \\name",
+    "\\name:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    // the 5 words of the interrupt frame and the 15 registers keep the
+    // stack 16 bytes aligned for the call
+    "mov rdi, rsp",
+    "call \\handler",
+    "mov rsp, rax",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "iretq",
+    ".endm",
+    "SWITCHING_ENTRY timer_interrupt_entry, {timer_handler}",
+    "SWITCHING_ENTRY yield_interrupt_entry, {yield_handler}",
+    "SWITCHING_ENTRY apic_timer_interrupt_entry, {apic_timer_handler}",
+    timer_handler = sym crate::interrupts::timer_interrupt_handler,
+    apic_timer_handler = sym crate::interrupts::apic_timer_interrupt_handler,
+    yield_handler = sym super::scheduler::yield_interrupt_handler,
+);
+
+extern "C" {
+    fn timer_interrupt_entry();
+    fn yield_interrupt_entry();
+    fn apic_timer_interrupt_entry();
+}
+
+/// Registers saved on the stack of a thread that is switched out,
+/// from the top of the stack pointer up.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub(super) struct SwitchFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    // pushed by the CPU
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl SwitchFrame {
+    /// A frame that starts executing `entry(argument)` on the stack
+    /// pointed to by `stack_pointer`, with interrupts enabled.
+    pub fn new(entry: u64, argument: u64, stack_pointer: u64) -> Self {
+        const INTERRUPT_FLAG: u64 = 1 << 9;
+        /// Bit 1 of RFLAGS is always set
+        const RESERVED_FLAG: u64 = 1 << 1;
+
+        Self {
+            rdi: argument,
+            rip: entry,
+            cs: CS::get_reg().0 as u64,
+            rflags: INTERRUPT_FLAG | RESERVED_FLAG,
+            rsp: stack_pointer,
+            ss: SS::get_reg().0 as u64,
+            ..Self::default()
+        }
+    }
+}
+
+/// Entry to install in the IDT for the timer interrupt.
+pub(crate) fn timer_entry() -> VirtAddr {
+    VirtAddr::from_ptr(timer_interrupt_entry as *const ())
+}
+
+/// Entry to install in the IDT for `YIELD_VECTOR`.
+pub(crate) fn yield_entry() -> VirtAddr {
+    VirtAddr::from_ptr(yield_interrupt_entry as *const ())
+}
+
+/// Entry to install in the IDT for `apic::TIMER_VECTOR`.
+pub(crate) fn apic_timer_entry() -> VirtAddr {
+    VirtAddr::from_ptr(apic_timer_interrupt_entry as *const ())
+}
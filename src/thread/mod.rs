@@ -0,0 +1,307 @@
+//! Preemptive kernel threads.
+//!
+//! Every thread has its own stack, and the running thread is switched for
+//! the next one in a round-robin fashion on each timer interrupt, so a
+//! CPU-bound thread can not freeze the others. The code running before
+//! `init` (usually `kernel_main` and its `Executor`) becomes the boot
+//! thread, that runs on the bootloader's stack.
+//!
+//! With SMP, every CPU has its own scheduler and boot thread (see
+//! `smp::start_aps`), and a thread runs on the CPU it was spawned on.
+//!
+//! Threads block by parking (see `park`), which takes them out of the
+//! scheduler until they are unparked. `WaitQueue`, `Mutex`, `sleep`,
+//! `JoinHandle::join` and `block_on` are built on top of it.
+
+use core::arch::asm;
+use core::future::Future;
+use core::mem::size_of;
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use alloc::{boxed::Box, sync::Arc, task::Wake, vec};
+use x86_64::instructions::interrupts;
+
+use self::context::SwitchFrame;
+use crate::interrupts::{YIELD_VECTOR, TIMER_FREQUENCY};
+
+pub(crate) mod context;
+pub(crate) mod scheduler;
+mod mutex;
+mod wait_queue;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use wait_queue::WaitQueue;
+
+/// Size of the stack of each thread
+const STACK_SIZE: usize = 16 * 1024;
+
+/// Written at the bottom of each stack to detect overflows
+const STACK_CANARY: u64 = 0x5357_4147_5357_4147;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ThreadState {
+    Runnable,
+    /// Parked, waiting for `unpark`
+    Blocked,
+    Exited,
+}
+
+pub(crate) struct Thread {
+    id: ThreadId,
+    /// `None` for the boot thread
+    stack: Option<Box<[u64]>>,
+    /// Saved stack pointer while the thread is not running
+    stack_pointer: u64,
+    state: ThreadState,
+    /// Set by `unpark` when the thread was not parked, so that its next
+    /// `park` returns immediately
+    unpark_token: bool,
+}
+
+type ThreadMain = Box<dyn FnOnce() + Send>;
+
+/// Owned permission to wait for a thread to finish, see `spawn`.
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    packet: Arc<Packet<T>>,
+}
+
+/// Where a thread stores its result for `JoinHandle::join`
+struct Packet<T> {
+    result: spin::Mutex<Option<T>>,
+    finished: AtomicBool,
+    joiners: WaitQueue,
+}
+
+/// Unparks a thread blocked in `block_on`
+struct ThreadWaker(ThreadId);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Thread {
+    /// Creates a thread running `main` on a new stack.
+    fn new(main: ThreadMain) -> Self {
+        let mut stack = vec![0u64; STACK_SIZE / size_of::<u64>()].into_boxed_slice();
+        stack[0] = STACK_CANARY;
+
+        // leave room for the fake return address of `thread_start`, that
+        // the System V ABI expects 8 bytes below a 16 bytes boundary
+        let stack_top = (stack.as_ptr_range().end as u64) & !0xf;
+        let entry_stack_pointer = stack_top - 8;
+        let frame_address = stack_top - 16 - size_of::<SwitchFrame>() as u64;
+
+        let main = Box::into_raw(Box::new(main));
+        let frame = SwitchFrame::new(
+            thread_start as *const () as u64,
+            main as u64,
+            entry_stack_pointer,
+        );
+        unsafe { (frame_address as *mut SwitchFrame).write(frame) };
+
+        Self {
+            id: ThreadId::new(),
+            stack: Some(stack),
+            stack_pointer: frame_address,
+            state: ThreadState::Runnable,
+            unpark_token: false,
+        }
+    }
+
+    fn stack_intact(&self) -> bool {
+        match &self.stack {
+            Some(stack) => stack[0] == STACK_CANARY,
+            None => true,
+        }
+    }
+}
+
+/// Turns the running code into the boot thread of the running CPU, and
+/// starts scheduling on it.
+///
+/// Must be called once per CPU, after the heap is initialized.
+pub fn init() {
+    let boot_thread = Thread {
+        id: ThreadId::new(),
+        stack: None,
+        stack_pointer: 0,
+        state: ThreadState::Runnable,
+        unpark_token: false,
+    };
+    let idle_thread = Thread::new(Box::new(|| crate::hlt_loop()));
+    interrupts::without_interrupts(|| {
+        let mut scheduler = scheduler::this_cpu().lock();
+        assert!(scheduler.current.is_none(), "thread::init called twice");
+        scheduler.current = Some(boot_thread);
+        scheduler.idle_id = Some(idle_thread.id);
+        scheduler.idle = Some(idle_thread);
+    });
+}
+
+/// Starts a new thread running `main`.
+pub fn spawn<F, T>(main: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: spin::Mutex::new(None),
+        finished: AtomicBool::new(false),
+        joiners: WaitQueue::new(),
+    });
+    let thread_packet = packet.clone();
+    let main = move || {
+        let result = main();
+        interrupts::without_interrupts(|| {
+            *thread_packet.result.lock() = Some(result);
+        });
+        thread_packet.finished.store(true, Ordering::Release);
+        thread_packet.joiners.notify_all();
+    };
+
+    // allocate before locking the scheduler
+    let thread = Thread::new(Box::new(main));
+    let id = thread.id;
+    interrupts::without_interrupts(|| {
+        scheduler::this_cpu().lock().ready.push_back(thread);
+    });
+    JoinHandle { id, packet }
+}
+
+/// Gives the rest of the time slice of the current thread to the next one.
+pub fn yield_now() {
+    unsafe { asm!("int {}", const YIELD_VECTOR) };
+}
+
+/// Returns the ID of the running thread.
+///
+/// Panics if `init` was not called.
+pub fn current_id() -> ThreadId {
+    interrupts::without_interrupts(|| {
+        scheduler::this_cpu().lock().current.as_ref()
+            .expect("threads not initialized")
+            .id
+    })
+}
+
+/// Blocks the current thread until `unpark` is called with its ID.
+///
+/// Returns immediately if it was unparked since its last `park`. It may
+/// also return spuriously, so callers should check their wait condition
+/// in a loop.
+pub fn park() {
+    // interrupts stay disabled until we are switched out, so that an
+    // `unpark` from an interrupt handler can not be missed
+    interrupts::without_interrupts(|| {
+        {
+            let mut scheduler = scheduler::this_cpu().lock();
+            let current = scheduler.current.as_mut().expect("threads not initialized");
+            if current.unpark_token {
+                current.unpark_token = false;
+                return;
+            }
+            current.state = ThreadState::Blocked;
+        }
+        yield_now();
+    });
+}
+
+/// Wakes up the thread `id` if it is parked. Can be called from
+/// interrupt handlers.
+pub fn unpark(id: ThreadId) {
+    interrupts::without_interrupts(|| {
+        scheduler::unpark(id);
+    });
+}
+
+/// Blocks the current thread for at least `duration`.
+///
+/// The resolution is one tick of the timer interrupt.
+pub fn sleep(duration: Duration) {
+    let ticks_per_second = TIMER_FREQUENCY as u128;
+    let ticks = (duration.as_nanos() * ticks_per_second).div_ceil(1_000_000_000);
+    let deadline = crate::interrupts::ticks() + ticks as u64;
+
+    let id = current_id();
+    interrupts::without_interrupts(|| {
+        scheduler::this_cpu().lock().sleepers.push((deadline, id));
+    });
+    while crate::interrupts::ticks() < deadline {
+        park();
+    }
+}
+
+/// Blocks the current thread until `future` completes, and returns its
+/// output.
+///
+/// This lets threads wait on the same futures as tasks: the thread is
+/// parked while the future is pending, and unparked by its waker.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(current_id())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        park();
+    }
+}
+
+/// Ends the current thread.
+pub fn exit() -> ! {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = scheduler::this_cpu().lock();
+        let current = scheduler.current.as_mut().expect("threads not initialized");
+        assert!(current.stack.is_some(), "the boot thread can not exit");
+        current.state = ThreadState::Exited;
+    });
+    yield_now();
+    unreachable!("exited thread scheduled again");
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.packet.finished.load(Ordering::Acquire)
+    }
+
+    /// Waits for the thread to finish, and returns the value returned by
+    /// its `main`.
+    pub fn join(self) -> T {
+        let packet = &self.packet;
+        packet.joiners.wait_while(|| !packet.finished.load(Ordering::Acquire));
+        interrupts::without_interrupts(|| packet.result.lock().take())
+            .expect("thread finished without a result")
+    }
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        unpark(self.0);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        unpark(self.0);
+    }
+}
+
+/// First code run by a new thread, called with the `main` given to `spawn`
+extern "C" fn thread_start(main: *mut ThreadMain) -> ! {
+    let main = unsafe { Box::from_raw(main) };
+    main();
+    exit();
+}
@@ -0,0 +1,159 @@
+use alloc::{collections::{BTreeMap, VecDeque}, vec::Vec};
+use spin::Mutex;
+
+use super::{Thread, ThreadId, ThreadState};
+use crate::smp::{self, MAX_CPUS};
+
+/// The scheduler of the threads of each CPU
+///
+/// Only locked with interrupts disabled, since the timer interrupt
+/// handler locks it too. A CPU may lock the scheduler of another one to
+/// unpark a thread, but never holds two of them at once.
+static SCHEDULERS: [Mutex<Scheduler>; MAX_CPUS] =
+    [const { Mutex::new(Scheduler::new()) }; MAX_CPUS];
+
+/// Round-robin scheduler, threads are switched on every timer interrupt
+/// and whenever they yield or block. Threads stay on the CPU they were
+/// spawned on.
+pub(super) struct Scheduler {
+    /// `None` until `thread::init` is called
+    pub current: Option<Thread>,
+    pub ready: VecDeque<Thread>,
+    /// Threads waiting to be unparked
+    blocked: BTreeMap<ThreadId, Thread>,
+    /// Runs when no other thread can
+    pub idle: Option<Thread>,
+    pub idle_id: Option<ThreadId>,
+    /// Deadlines of the threads in `thread::sleep`, in timer ticks
+    pub sleepers: Vec<(u64, ThreadId)>,
+    /// Threads that exited, freed at the next switch since we may still
+    /// be running on their stack
+    exited: Vec<Thread>,
+}
+
+impl Scheduler {
+    const fn new() -> Self {
+        Self {
+            current: None,
+            ready: VecDeque::new(),
+            blocked: BTreeMap::new(),
+            idle: None,
+            idle_id: None,
+            sleepers: Vec::new(),
+            exited: Vec::new(),
+        }
+    }
+
+    /// Saves the stack pointer of the current thread, and returns the one
+    /// of the next thread to run.
+    fn switch(&mut self, stack_pointer: u64) -> u64 {
+        self.exited.clear();
+
+        let mut current = match self.current.take() {
+            Some(current) => current,
+            None => return stack_pointer,
+        };
+        current.stack_pointer = stack_pointer;
+        assert!(current.stack_intact(), "stack overflow in thread {:?}", current.id);
+        let current_is_idle = Some(current.id) == self.idle_id;
+
+        // an `unpark` from another CPU may come between `park` and here
+        if current.state == ThreadState::Blocked && current.unpark_token {
+            current.unpark_token = false;
+            current.state = ThreadState::Runnable;
+        }
+
+        let next = match self.ready.pop_front() {
+            Some(next) => next,
+            None if current_is_idle || current.state == ThreadState::Runnable => {
+                // nothing else to run
+                self.current = Some(current);
+                return stack_pointer;
+            }
+            None => self.idle.take().expect("idle thread not initialized"),
+        };
+
+        if current_is_idle {
+            self.idle = Some(current);
+        } else {
+            match current.state {
+                ThreadState::Runnable => self.ready.push_back(current),
+                ThreadState::Blocked => {
+                    self.blocked.insert(current.id, current);
+                }
+                ThreadState::Exited => self.exited.push(current),
+            }
+        }
+
+        let stack_pointer = next.stack_pointer;
+        self.current = Some(next);
+        stack_pointer
+    }
+
+    /// Makes a blocked thread runnable again, or lets the next `park`
+    /// of a running thread return immediately. Returns `false` if the
+    /// thread is not on this scheduler.
+    pub fn unpark(&mut self, id: ThreadId) -> bool {
+        if let Some(mut thread) = self.blocked.remove(&id) {
+            thread.state = ThreadState::Runnable;
+            self.ready.push_back(thread);
+            return true;
+        }
+        let thread = self.current.iter_mut()
+            .chain(self.ready.iter_mut())
+            .find(|thread| thread.id == id);
+        match thread {
+            Some(thread) => {
+                thread.unpark_token = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unparks the sleepers whose deadline is `now` or earlier.
+    fn wake_sleepers(&mut self, now: u64) {
+        let mut i = 0;
+        while i < self.sleepers.len() {
+            let (deadline, id) = self.sleepers[i];
+            if deadline <= now {
+                self.sleepers.swap_remove(i);
+                // sleepers are parked on this CPU
+                self.unpark(id);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Returns the scheduler of the running CPU.
+pub(super) fn this_cpu() -> &'static Mutex<Scheduler> {
+    &SCHEDULERS[smp::cpu_index()]
+}
+
+/// Unparks the thread `id`, wherever it runs.
+pub(super) fn unpark(id: ThreadId) {
+    // most threads are unparked by their own CPU
+    let this_cpu = smp::cpu_index();
+    let others = SCHEDULERS.iter().enumerate()
+        .filter(|&(cpu, _)| cpu != this_cpu)
+        .map(|(_, scheduler)| scheduler);
+    for scheduler in core::iter::once(&SCHEDULERS[this_cpu]).chain(others) {
+        if scheduler.lock().unpark(id) {
+            return;
+        }
+    }
+}
+
+/// Called on timer interrupts, after the end of interrupt was sent.
+pub(crate) fn preempt(stack_pointer: u64, now: u64) -> u64 {
+    let mut scheduler = this_cpu().lock();
+    scheduler.wake_sleepers(now);
+    scheduler.switch(stack_pointer)
+}
+
+/// Called on `YIELD_VECTOR` interrupts, see `thread::yield_now`.
+pub(crate) extern "C" fn yield_interrupt_handler(stack_pointer: u64) -> u64 {
+    this_cpu().lock().switch(stack_pointer)
+}
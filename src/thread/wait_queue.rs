@@ -0,0 +1,67 @@
+use alloc::collections::VecDeque;
+use x86_64::instructions::interrupts;
+
+use super::{ThreadId, current_id, park, unpark};
+
+/// A queue of threads blocked until some condition changes.
+///
+/// Waiters register themselves before checking their condition, so a
+/// notification sent right after the check is not lost.
+pub struct WaitQueue {
+    waiters: spin::Mutex<VecDeque<ThreadId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: spin::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Blocks the current thread while `condition` returns true.
+    ///
+    /// The condition is checked again every time the thread is notified.
+    pub fn wait_while(&self, mut condition: impl FnMut() -> bool) {
+        let id = current_id();
+        loop {
+            interrupts::without_interrupts(|| {
+                let mut waiters = self.waiters.lock();
+                if !waiters.contains(&id) {
+                    waiters.push_back(id);
+                }
+            });
+            if !condition() {
+                break;
+            }
+            park();
+        }
+        interrupts::without_interrupts(|| {
+            self.waiters.lock().retain(|waiter| *waiter != id);
+        });
+    }
+
+    /// Wakes up the longest waiting thread, returns false if there is none.
+    pub fn notify_one(&self) -> bool {
+        let waiter = interrupts::without_interrupts(|| {
+            self.waiters.lock().pop_front()
+        });
+        match waiter {
+            Some(id) => {
+                unpark(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes up all the waiting threads.
+    pub fn notify_all(&self) {
+        while self.notify_one() {}
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,208 @@
+//! VT100 escape sequences, as emitted for terminals (see `serial::Green`).
+//!
+//! `Parser` is fed the written characters one at a time and turns them into the
+//! `Action`s the `Writer` performs. The sequences it does not know are
+//! dropped, so that they never show up as garbage.
+
+/// Most parameters kept for a control sequence, the next ones are dropped
+const MAX_PARAMS: usize = 16;
+
+const ESCAPE: char = '\x1b';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Outside of any escape sequence
+    Ground,
+    /// After ESC
+    Escape,
+    /// After ESC [, the control sequence introducer
+    ControlSequence,
+}
+
+/// Numeric parameters of a control sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Params {
+    values: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+/// What to do with the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    /// A character outside of any escape sequence
+    Print(char),
+    /// CUP, to a zero based position
+    CursorPosition { row: usize, column: usize },
+    /// CUU
+    CursorUp(usize),
+    /// CUD
+    CursorDown(usize),
+    /// CUF
+    CursorForward(usize),
+    /// CUB
+    CursorBack(usize),
+    /// ED
+    EraseDisplay(Erase),
+    /// EL
+    EraseLine(Erase),
+    /// DECSC or SCOSC
+    SaveCursor,
+    /// DECRC or SCORC
+    RestoreCursor,
+    /// SGR, with at least one parameter
+    SelectGraphicRendition(Params),
+}
+
+/// Part of the line or screen erased, relative to the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Erase {
+    ToEnd,
+    ToStart,
+    All,
+}
+
+pub(super) struct Parser {
+    state: State,
+    params: Params,
+}
+
+impl Parser {
+    pub(super) const fn new() -> Self {
+        Self { state: State::Ground, params: Params::new() }
+    }
+
+    /// Takes the next written `c`, and returns the action it completes.
+    pub(super) fn advance(&mut self, c: char) -> Option<Action> {
+        match self.state {
+            State::Ground if c == ESCAPE => {
+                self.state = State::Escape;
+                None
+            }
+            State::Ground => Some(Action::Print(c)),
+            State::Escape => {
+                self.state = State::Ground;
+                match c {
+                    '[' => {
+                        self.state = State::ControlSequence;
+                        self.params = Params::new();
+                        None
+                    }
+                    '7' => Some(Action::SaveCursor),
+                    '8' => Some(Action::RestoreCursor),
+                    _ => None,
+                }
+            }
+            State::ControlSequence => match c {
+                '0'..='9' => {
+                    self.params.push_digit(c as u8 - b'0');
+                    None
+                }
+                ';' => {
+                    self.params.next();
+                    None
+                }
+                // private markers and intermediate bytes
+                '<'..='?' | ' '..='/' => None,
+                '@'..='~' => {
+                    self.state = State::Ground;
+                    // so a sequence without parameters has a single 0 one
+                    self.params.next();
+                    self.dispatch(c as u8)
+                }
+                // not a control sequence after all
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+        }
+    }
+
+    /// Returns the action of the control sequence ending with `last`.
+    fn dispatch(&self, last: u8) -> Option<Action> {
+        let params = &self.params;
+        // counts and positions are 1 when missing or 0
+        let count = |index| params.get(index).max(1) as usize;
+        let erase = || match params.get(0) {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToStart),
+            2 | 3 => Some(Erase::All),
+            _ => None,
+        };
+        match last {
+            b'H' | b'f' => Some(Action::CursorPosition {
+                row: count(0) - 1,
+                column: count(1) - 1,
+            }),
+            b'A' => Some(Action::CursorUp(count(0))),
+            b'B' => Some(Action::CursorDown(count(0))),
+            b'C' => Some(Action::CursorForward(count(0))),
+            b'D' => Some(Action::CursorBack(count(0))),
+            b'J' => erase().map(Action::EraseDisplay),
+            b'K' => erase().map(Action::EraseLine),
+            b's' => Some(Action::SaveCursor),
+            b'u' => Some(Action::RestoreCursor),
+            b'm' => Some(Action::SelectGraphicRendition(*params)),
+            _ => None,
+        }
+    }
+}
+
+impl Params {
+    const fn new() -> Self {
+        Self { values: [0; MAX_PARAMS], len: 0 }
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if let Some(value) = self.values.get_mut(self.len) {
+            *value = value.saturating_mul(10).saturating_add(digit as u16);
+        }
+    }
+
+    /// Ends the current parameter.
+    fn next(&mut self) {
+        self.len = (self.len + 1).min(MAX_PARAMS);
+    }
+
+    /// The parameter at `index`, 0 when missing
+    fn get(&self, index: usize) -> u16 {
+        self.values[..self.len].get(index).copied().unwrap_or(0)
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.values[..self.len].iter().copied()
+    }
+}
+
+#[test_case]
+fn test_parse_sequences() {
+    let mut parser = Parser::new();
+    let mut parse = |s: &str| {
+        let mut actions = s.chars().filter_map(|c| parser.advance(c));
+        let action = actions.next();
+        assert_eq!(actions.next(), None);
+        action
+    };
+
+    assert_eq!(parse("a"), Some(Action::Print('a')));
+    assert_eq!(parse("\x1b[5;10H"), Some(Action::CursorPosition { row: 4, column: 9 }));
+    assert_eq!(parse("\x1b[H"), Some(Action::CursorPosition { row: 0, column: 0 }));
+    assert_eq!(parse("\x1b[3A"), Some(Action::CursorUp(3)));
+    assert_eq!(parse("\x1b[B"), Some(Action::CursorDown(1)));
+    assert_eq!(parse("\x1b[2J"), Some(Action::EraseDisplay(Erase::All)));
+    assert_eq!(parse("\x1b[K"), Some(Action::EraseLine(Erase::ToEnd)));
+    assert_eq!(parse("\x1b7"), Some(Action::SaveCursor));
+    assert_eq!(parse("\x1b[u"), Some(Action::RestoreCursor));
+    assert_eq!(parse("\x1b[?25l"), None);
+
+    match parse("\x1b[1;31m") {
+        Some(Action::SelectGraphicRendition(params)) => {
+            assert!(params.iter().eq([1, 31]));
+        }
+        action => panic!("unexpected {:?}", action),
+    }
+    match parse("\x1b[m") {
+        Some(Action::SelectGraphicRendition(params)) => assert!(params.iter().eq([0])),
+        action => panic!("unexpected {:?}", action),
+    }
+}
@@ -0,0 +1,51 @@
+//! Code page 437, the character set of the VGA text mode font.
+
+/// Characters shown for the bytes below 0x20, the first one is never shown
+const LOW: [char; 0x20] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Character shown for 0x7f
+const DELETE: char = '⌂';
+
+/// Characters shown for the bytes from 0x80
+const HIGH: [char; 0x80] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Byte of the glyph for the characters that have none
+pub(crate) const REPLACEMENT: u8 = 0xfe;
+
+/// Returns the byte of the glyph of `c`, if the font has one.
+pub(crate) fn encode(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' => Some(c as u8),
+        DELETE => Some(0x7f),
+        // same glyphs as other characters
+        'β' => Some(0xe1),
+        'μ' => Some(0xe6),
+        '\0' => None,
+        c => LOW.iter().position(|&low| low == c)
+            .or_else(|| HIGH.iter().position(|&high| high == c).map(|i| i + 0x80))
+            .map(|i| i as u8),
+    }
+}
+
+#[test_case]
+fn test_encode() {
+    assert_eq!(encode('a'), Some(b'a'));
+    assert_eq!(encode('é'), Some(0x82));
+    assert_eq!(encode('═'), Some(0xcd));
+    assert_eq!(encode('█'), Some(0xdb));
+    assert_eq!(encode('☺'), Some(0x01));
+    assert_eq!(encode('€'), None);
+    assert_eq!(encode('\0'), None);
+}
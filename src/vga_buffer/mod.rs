@@ -0,0 +1,1098 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use volatile::Volatile;
+
+use crate::console::Console;
+use crate::graphics::Rgb;
+
+use self::ansi::{Action, Erase, Params};
+use self::scrollback::Scrollback;
+use self::terminals::{Screen, DISPLAY, TERMINALS};
+
+mod ansi;
+pub(crate) mod cp437;
+mod scrollback;
+mod terminals;
+
+pub use self::terminals::{
+    active_terminal, init_terminals, switch_terminal, terminal, TERMINAL_COUNT,
+};
+
+/// Color used in the print! macros
+pub const NORMAL_COLOR: ColorCode = ColorCode::new(Color::White, Color::Black);
+/// Color used in the eprintln! macro
+pub const ERR_COLOR: ColorCode  = ColorCode::new(Color::Red, Color::White);
+
+/// Most colors set at the same time, see `Writer::push_color`
+const MAX_STYLES: usize = 16;
+
+/// Set by `init_scrollback`, once the terminals can allocate their history
+static KEEP_SCROLLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Memory address at which we write output
+const VGA_ADDRESS : usize = 0xb8000;
+
+const BUFFER_HEIGHT: usize = 25;
+const BUFFER_WIDTH: usize = 80;
+
+/// Tab stops are every `TAB_WIDTH` columns
+const TAB_WIDTH: usize = 8;
+
+/// CRTC ports, the index of a register then its value
+const CRTC_INDEX_PORT: u16 = 0x3d4;
+const CRTC_DATA_PORT: u16 = 0x3d5;
+
+/// CRTC registers
+const CURSOR_START: u8 = 0x0a;
+const CURSOR_END: u8 = 0x0b;
+const CURSOR_LOCATION_HIGH: u8 = 0x0e;
+const CURSOR_LOCATION_LOW: u8 = 0x0f;
+
+// `Writer` of the first virtual terminal, used in the print! macros
+lazy_static! {
+    pub static ref WRITER: &'static Mutex<Writer> = terminal(0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+/// `ColorCode` is a u8 wrapper that contains
+/// text color and background color
+/// background color being the first 4 bits and
+/// text color being the last 4 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+/// Identifies a color pushed with `Writer::push_color`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StyleId(u64);
+
+/// Sets back the previous color when dropped, see `set_color`
+#[must_use = "the previous color is set back when the guard is dropped"]
+pub struct ColorGuard {
+    /// `None` if there were too many colors set to push one more
+    id: Option<StyleId>,
+}
+
+/// Colors pushed with `Writer::push_color`, the last one being written with
+struct StyleStack {
+    styles: [Style; MAX_STYLES],
+    len: usize,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    id: StyleId,
+    color_code: ColorCode,
+    /// Colors and boldness written with before the style was pushed, set
+    /// back when it is popped
+    previous: (Colors, bool),
+}
+
+/// Colors written with: the ones of the text mode, and the true colors set
+/// by the SGR escape sequences that the framebuffer shows instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Colors {
+    /// The closest ones to the true colors, if they are set
+    code: ColorCode,
+    text: Option<Rgb>,
+    background: Option<Rgb>,
+}
+
+/// A character cell: the code page 437 glyph and its colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenChar {
+    pub ascii_character: u8,
+    /// The colors in text mode
+    pub color_code: ColorCode,
+    /// The colors on the framebuffer, usually the ones of `color_code`
+    pub text: Rgb,
+    pub background: Rgb,
+}
+
+/// A cell of the VGA text buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct VgaChar {
+    ascii_character: u8,
+    color_code: ColorCode,
+}
+
+#[repr(transparent)]
+struct Buffer {
+    chars: [[Volatile<VgaChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+/// Writes to the whole `Console`, from the top left corner, and scrolls
+/// once the bottom row is full. The cursor follows the next character to
+/// write.
+pub struct Writer {
+    column_position: usize,
+    row_position: usize,
+    colors: Colors,
+    /// Set by the SGR escape sequence, brightens the text color
+    bold: bool,
+    /// Position and colors stored by the save cursor escape sequences
+    saved_cursor: (usize, usize, Colors, bool),
+    /// Interprets the escape sequences in the written strings
+    parser: ansi::Parser,
+    /// Set by `init_scrollback` for the terminal shown, and by
+    /// `switch_terminal` for the others
+    scrollback: Option<Scrollback>,
+    styles: StyleStack,
+    /// The screen of the virtual terminal, drawn on the console while shown
+    console: Screen,
+}
+
+impl Color {
+    /// Every color, by value
+    const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// Color of an ANSI color index, from 0 to 7, in the dark or bright
+    /// variant.
+    fn from_ansi(index: u16, bright: bool) -> Self {
+        // ANSI orders red, green and blue the other way around
+        const DARK: [Color; 8] = [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Brown,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::LightGray,
+        ];
+        let color = DARK[index as usize % 8];
+        if bright { color.bright() } else { color }
+    }
+
+    fn bright(self) -> Self {
+        Color::ALL[self as usize | 8]
+    }
+
+    /// The color of the palette closest to `color`
+    fn nearest(color: Rgb) -> Self {
+        let distance = |palette: Rgb| {
+            let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            channel(palette.red, color.red)
+                + channel(palette.green, color.green)
+                + channel(palette.blue, color.blue)
+        };
+        Color::ALL.into_iter()
+            .min_by_key(|&palette| distance(palette.into()))
+            .unwrap_or(Color::White)
+    }
+}
+
+impl ColorCode {
+    pub const fn new(text: Color, background: Color) -> Self {
+        Self((background as u8) << 4 | (text as u8))
+    }
+
+    pub fn text(self) -> Color {
+        Color::ALL[(self.0 & 0xf) as usize]
+    }
+
+    pub fn background(self) -> Color {
+        Color::ALL[(self.0 >> 4) as usize]
+    }
+}
+
+impl Colors {
+    const fn new(code: ColorCode) -> Self {
+        Self { code, text: None, background: None }
+    }
+
+    /// The cell of `ascii_character` in these colors
+    fn cell(self, ascii_character: u8) -> ScreenChar {
+        ScreenChar {
+            ascii_character,
+            color_code: self.code,
+            text: self.text.unwrap_or_else(|| self.code.text().into()),
+            background: self.background.unwrap_or_else(|| self.code.background().into()),
+        }
+    }
+}
+
+impl ScreenChar {
+    /// `ascii_character` in the colors of `color_code`
+    pub fn new(ascii_character: u8, color_code: ColorCode) -> Self {
+        Colors::new(color_code).cell(ascii_character)
+    }
+}
+
+
+impl Writer {
+    fn new(console: Screen) -> Self {
+        let mut writer = Self {
+            column_position: 0,
+            row_position: 0,
+            colors: Colors::new(NORMAL_COLOR),
+            bold: false,
+            saved_cursor: (0, 0, Colors::new(NORMAL_COLOR), false),
+            parser: ansi::Parser::new(),
+            scrollback: None,
+            styles: StyleStack {
+                styles: [Style {
+                    id: StyleId(0),
+                    color_code: NORMAL_COLOR,
+                    previous: (Colors::new(NORMAL_COLOR), false),
+                }; MAX_STYLES],
+                len: 0,
+                next_id: 0,
+            },
+            console,
+        };
+        writer.clear();
+        writer
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        self.snap_back();
+        for c in s.chars() {
+            if let Some(action) = self.parser.advance(c) {
+                self.perform(action);
+            }
+        }
+        self.move_cursor();
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        self.snap_back();
+        self.put_byte(byte);
+        self.move_cursor();
+    }
+
+    /// Blanks the screen, and moves back to the top left corner.
+    pub fn clear(&mut self) {
+        self.snap_back();
+        for row in 0..self.height() {
+            self.clear_row(row);
+        }
+        self.row_position = 0;
+        self.column_position = 0;
+        self.move_cursor();
+    }
+
+    /// Number of columns
+    pub fn width(&self) -> usize {
+        self.console.width()
+    }
+
+    /// Number of rows
+    pub fn height(&self) -> usize {
+        self.console.height()
+    }
+
+    /// Color of the next characters written, in text mode
+    pub fn color(&self) -> ColorCode {
+        self.colors.code
+    }
+
+    /// Writes with `color_code` until `pop_color` is called with the
+    /// returned id, or another color is pushed. Returns `None` if
+    /// `MAX_STYLES` colors are pushed already.
+    pub fn push_color(&mut self, color_code: ColorCode) -> Option<StyleId> {
+        let styles = &mut self.styles;
+        if styles.len == MAX_STYLES {
+            return None;
+        }
+        let id = StyleId(styles.next_id);
+        styles.next_id += 1;
+        styles.styles[styles.len] = Style {
+            id,
+            color_code,
+            previous: (self.colors, self.bold),
+        };
+        styles.len += 1;
+        self.colors = Colors::new(color_code);
+        self.bold = false;
+        Some(id)
+    }
+
+    /// Forgets the color pushed as `id`. If it was the last one, writes
+    /// with the color written with before it was pushed again, even one
+    /// set by an escape sequence.
+    ///
+    /// Colors can be popped in any order, for instance by two threads
+    /// printing at the same time.
+    pub fn pop_color(&mut self, id: StyleId) {
+        let styles = &mut self.styles;
+        let Some(index) = styles.styles[..styles.len].iter().position(|style| style.id == id) else {
+            return;
+        };
+        let previous = styles.styles[index].previous;
+        if index + 1 < styles.len {
+            // the next one goes back to what was there before this one
+            styles.styles[index + 1].previous = previous;
+        } else {
+            (self.colors, self.bold) = previous;
+        }
+        styles.styles.copy_within(index + 1..styles.len, index);
+        styles.len -= 1;
+    }
+
+    /// The last color pushed, or `NORMAL_COLOR`
+    fn base_color(&self) -> ColorCode {
+        match self.styles.len {
+            0 => NORMAL_COLOR,
+            len => self.styles.styles[len - 1].color_code,
+        }
+    }
+
+    /// Shows the `lines` above the view, the oldest ones being forgotten
+    /// after `SCROLLBACK_LINES`.
+    pub fn scroll_up(&mut self, lines: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.scroll_up(&mut self.console, lines);
+            self.move_cursor();
+        }
+    }
+
+    /// Shows the `lines` below the view, as far as the bottom of the screen.
+    pub fn scroll_down(&mut self, lines: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.scroll_down(&mut self.console, lines);
+            self.move_cursor();
+        }
+    }
+
+    /// Scrolls the view back to the bottom of the screen, to write to it.
+    fn snap_back(&mut self) {
+        if let Some(scrollback) = &mut self.scrollback {
+            if scrollback.is_scrolled() {
+                scrollback.snap_back(&mut self.console);
+            }
+        }
+    }
+
+    /// Starts a scrollback history if there is none yet, once
+    /// `init_scrollback` was called.
+    fn keep_scrollback(&mut self) {
+        if self.scrollback.is_none() && KEEP_SCROLLBACK.load(Ordering::Acquire) {
+            self.reset_scrollback();
+        }
+    }
+
+    /// Starts an empty scrollback history, as wide as the screen.
+    fn reset_scrollback(&mut self) {
+        self.scrollback = Some(Scrollback::new(&self.console, self.colors.cell(b' ')));
+    }
+
+    /// Writes `byte` without moving the hardware cursor.
+    fn put_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column_position = 0,
+            b'\t' => {
+                let spaces = TAB_WIDTH - self.column_position % TAB_WIDTH;
+                for _ in 0..spaces {
+                    self.put_byte(b' ');
+                }
+            }
+            0x08 => self.backspace(),
+            byte => self.put_glyph(byte),
+        }
+    }
+
+    /// Writes `c` without moving the hardware cursor, as its code page 437
+    /// glyph, or '■' if it has none.
+    fn put_char(&mut self, c: char) {
+        match c {
+            '\n' | '\r' | '\t' | '\x08' => self.put_byte(c as u8),
+            c => self.put_glyph(cp437::encode(c).unwrap_or(cp437::REPLACEMENT)),
+        }
+    }
+
+    /// Writes the glyph of `byte`, including the ones of control characters.
+    fn put_glyph(&mut self, byte: u8) {
+        if self.column_position >= self.width() {
+            self.new_line();
+        }
+
+        let row = self.row_position;
+        let col = self.column_position;
+
+        self.console.write(row, col, self.colors.cell(byte));
+
+        self.column_position += 1;
+    }
+
+    /// Performs the `action` of an escape sequence.
+    fn perform(&mut self, action: Action) {
+        // the column is past the last one at the end of a full row
+        let column = self.column_position.min(self.width() - 1);
+        match action {
+            Action::Print(c) => self.put_char(c),
+            Action::CursorPosition { row, column } => {
+                self.row_position = row.min(self.height() - 1);
+                self.column_position = column.min(self.width() - 1);
+            }
+            Action::CursorUp(count) => {
+                self.row_position = self.row_position.saturating_sub(count);
+            }
+            Action::CursorDown(count) => {
+                self.row_position = (self.row_position + count).min(self.height() - 1);
+            }
+            Action::CursorForward(count) => {
+                self.column_position = (column + count).min(self.width() - 1);
+            }
+            Action::CursorBack(count) => self.column_position = column.saturating_sub(count),
+            Action::EraseDisplay(erase) => {
+                let (rows, first, last) = match erase {
+                    Erase::ToEnd => (self.row_position + 1..self.height(), column, self.width()),
+                    Erase::ToStart => (0..self.row_position, 0, column + 1),
+                    Erase::All => (0..self.height(), 0, 0),
+                };
+                for row in rows {
+                    self.clear_row(row);
+                }
+                self.clear_columns(self.row_position, first..last);
+            }
+            Action::EraseLine(erase) => {
+                let columns = match erase {
+                    Erase::ToEnd => column..self.width(),
+                    Erase::ToStart => 0..column + 1,
+                    Erase::All => 0..self.width(),
+                };
+                self.clear_columns(self.row_position, columns);
+            }
+            Action::SaveCursor => {
+                self.saved_cursor =
+                    (self.row_position, self.column_position, self.colors, self.bold);
+            }
+            Action::RestoreCursor => {
+                (self.row_position, self.column_position, self.colors, self.bold) =
+                    self.saved_cursor;
+            }
+            Action::SelectGraphicRendition(params) => self.select_graphic_rendition(params),
+        }
+    }
+
+    /// Changes the colors as told by SGR `params`.
+    fn select_graphic_rendition(&mut self, params: Params) {
+        let mut text = self.colors.code.text();
+        let mut background = self.colors.code.background();
+        let (mut true_text, mut true_background) = (self.colors.text, self.colors.background);
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            // the palette colors replace the true ones
+            match param {
+                0 => (true_text, true_background) = (None, None),
+                30..=37 | 39 | 90..=97 => true_text = None,
+                40..=47 | 49 | 100..=107 => true_background = None,
+                _ => {}
+            }
+            match param {
+                0 => {
+                    self.bold = false;
+                    text = self.base_color().text();
+                    background = self.base_color().background();
+                }
+                1 => {
+                    self.bold = true;
+                    text = text.bright();
+                }
+                22 => {
+                    self.bold = false;
+                    text = Color::ALL[text as usize & 7];
+                }
+                30..=37 => text = Color::from_ansi(param - 30, self.bold),
+                38 => if let Some(color) = extended_color(&mut params) {
+                    text = Color::nearest(color);
+                    true_text = Some(color);
+                }
+                39 => text = self.base_color().text(),
+                40..=47 => background = Color::from_ansi(param - 40, false),
+                48 => if let Some(color) = extended_color(&mut params) {
+                    background = Color::nearest(color);
+                    true_background = Some(color);
+                }
+                49 => background = self.base_color().background(),
+                90..=97 => text = Color::from_ansi(param - 90, true),
+                100..=107 => background = Color::from_ansi(param - 100, true),
+                _ => {}
+            }
+        }
+        self.colors = Colors {
+            code: ColorCode::new(text, background),
+            text: true_text,
+            background: true_background,
+        };
+    }
+
+    /// Erases the character before the cursor, going back to the end of the
+    /// previous row at the start of a row.
+    fn backspace(&mut self) {
+        if self.column_position > 0 {
+            self.column_position -= 1;
+        } else if self.row_position > 0 {
+            self.row_position -= 1;
+            self.column_position = self.width() - 1;
+        } else {
+            return;
+        }
+        let blank = self.colors.cell(b' ');
+        self.console.write(self.row_position, self.column_position, blank);
+    }
+
+    fn new_line(&mut self) {
+        self.column_position = 0;
+        if self.row_position < self.height() - 1 {
+            self.row_position += 1;
+            return;
+        }
+
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.push(&self.console);
+        }
+        self.console.scroll();
+
+        self.clear_row(self.height() - 1);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        self.clear_columns(row, 0..self.width());
+    }
+
+    fn clear_columns(&mut self, row: usize, columns: core::ops::Range<usize>) {
+        let blank = self.colors.cell(b' ');
+
+        for col in columns {
+            self.console.write(row, col, blank);
+        }
+    }
+
+    /// Moves the cursor to the next character to write.
+    fn move_cursor(&mut self) {
+        // stays at the end of a full row until the next character wraps
+        let col = self.column_position.min(self.width() - 1);
+        let position = match &self.scrollback {
+            Some(scrollback) if scrollback.is_scrolled() => None,
+            _ => Some((self.row_position, col)),
+        };
+        self.console.set_cursor(position);
+    }
+}
+
+/// The color of the parameters after SGR 38 or 48: `2;r;g;b` for a true
+/// color. `None` for the `5;index` of a 256 colors palette, which is
+/// skipped.
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Rgb> {
+    match params.next()? {
+        2 => {
+            let mut channel = || params.next().unwrap_or(0).min(255) as u8;
+            Some(Rgb::new(channel(), channel(), channel()))
+        }
+        5 => {
+            params.next();
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Shows the hardware cursor as an underline.
+fn show_cursor() {
+    // the start register also holds the disable bit, cleared here
+    write_crtc(CURSOR_START, (read_crtc(CURSOR_START) & 0xc0) | 14);
+    write_crtc(CURSOR_END, (read_crtc(CURSOR_END) & 0xe0) | 15);
+}
+
+impl Console for Buffer {
+    fn width(&self) -> usize {
+        BUFFER_WIDTH
+    }
+
+    fn height(&self) -> usize {
+        BUFFER_HEIGHT
+    }
+
+    fn read(&self, row: usize, column: usize) -> ScreenChar {
+        let c = self.chars[row][column].read();
+        ScreenChar::new(c.ascii_character, c.color_code)
+    }
+
+    /// Writes `c` in the colors of its `color_code`, the true ones cannot
+    /// be shown in text mode.
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar) {
+        self.chars[row][column].write(VgaChar {
+            ascii_character: c.ascii_character,
+            color_code: c.color_code,
+        });
+    }
+
+    fn set_cursor(&mut self, position: Option<(usize, usize)>) {
+        // off the screen, so hidden
+        let (row, column) = position.unwrap_or((BUFFER_HEIGHT, 0));
+        let position = (row * BUFFER_WIDTH + column) as u16;
+        write_crtc(CURSOR_LOCATION_HIGH, (position >> 8) as u8);
+        write_crtc(CURSOR_LOCATION_LOW, position as u8);
+    }
+}
+
+fn read_crtc(register: u8) -> u8 {
+    use x86_64::instructions::port::Port;
+
+    let mut index: Port<u8> = Port::new(CRTC_INDEX_PORT);
+    let mut data: Port<u8> = Port::new(CRTC_DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.read()
+    }
+}
+
+fn write_crtc(register: u8, value: u8) {
+    use x86_64::instructions::port::Port;
+
+    let mut index: Port<u8> = Port::new(CRTC_INDEX_PORT);
+    let mut data: Port<u8> = Port::new(CRTC_DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.write(value);
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+// global macros
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Prints with the `ColorCode` given first.
+#[macro_export]
+macro_rules! cprint {
+    ($color:expr, $($arg:tt)*) => ($crate::vga_buffer::_cprint($color, format_args!($($arg)*), false));
+}
+
+/// Prints with the `ColorCode` given first, the new line with the color
+/// from before.
+#[macro_export]
+macro_rules! cprintln {
+    ($color:expr) => ($crate::println!());
+    ($color:expr, $($arg:tt)*) => ($crate::vga_buffer::_cprint($color, format_args!($($arg)*), true));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    ($($arg:tt)*) => ($crate::cprintln!($crate::vga_buffer::ERR_COLOR, $($arg)*));
+}
+
+/// Prints to the virtual terminal of the index given first.
+#[macro_export]
+macro_rules! tprint {
+    ($terminal:expr, $($arg:tt)*) => ($crate::vga_buffer::_print_to($terminal, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! tprintln {
+    ($terminal:expr) => ($crate::tprint!($terminal, "\n"));
+    ($terminal:expr, $($arg:tt)*) => ($crate::tprint!($terminal, "{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        WRITER.lock().write_fmt(args).unwrap();
+    });
+}
+
+#[doc(hidden)]
+pub fn _cprint(color_code: ColorCode, args: fmt::Arguments, new_line: bool) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let id = writer.push_color(color_code);
+        writer.write_fmt(args).unwrap();
+        if let Some(id) = id {
+            writer.pop_color(id);
+        }
+        // the row scrolled in is blanked with the color of the new line
+        if new_line {
+            writer.write_string("\n");
+        }
+    });
+}
+
+#[doc(hidden)]
+pub fn _print_to(index: usize, args: fmt::Arguments) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        terminal(index).lock().write_fmt(args).unwrap();
+    });
+}
+
+
+/// Keeps the lines scrolling off the screens from now on, so that they can
+/// be viewed again with `scroll_up`. Needs the heap.
+///
+/// Only the terminal shown gets its history now, the others get theirs the
+/// first time they are switched to.
+pub fn init_scrollback() {
+    use x86_64::instructions::interrupts;
+    KEEP_SCROLLBACK.store(true, Ordering::Release);
+    interrupts::without_interrupts(|| {
+        terminal(active_terminal()).lock().keep_scrollback();
+    });
+}
+
+/// Prints to `console` from now on, starting from blank screens.
+///
+/// The scrollback histories are cleared, since the lines may not have the
+/// same width anymore.
+pub fn set_console(console: &'static mut dyn Console) {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        let (width, height) = (console.width(), console.height());
+        // none of them can print on the new console before being resized
+        let mut writers = TERMINALS.each_ref().map(|terminal| terminal.lock());
+        for writer in &mut writers {
+            writer.snap_back();
+        }
+        *DISPLAY.lock() = console;
+        for writer in &mut writers {
+            writer.console.resize(width, height);
+            writer.clear();
+            if writer.scrollback.is_some() {
+                writer.reset_scrollback();
+            }
+        }
+    });
+}
+
+/// Makes the terminals draw on `console`, with what is shown copied to it.
+/// Unlike `set_console` it never allocates, so that it can be used to
+/// print a panic, but `console` must be at least as large as the current
+/// one, which the terminals keep the size of.
+pub fn replace_console(console: &'static mut dyn Console) {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        let mut display = DISPLAY.lock();
+        let (width, height) = (display.width(), display.height());
+        assert!(
+            console.width() >= width && console.height() >= height,
+            "console smaller than the current one"
+        );
+        for row in 0..height {
+            for column in 0..width {
+                console.write(row, column, display.read(row, column));
+            }
+        }
+        *display = console;
+    });
+}
+
+/// Scrolls the view of the terminal shown up by `lines`, until its next
+/// print.
+pub fn scroll_up(lines: usize) {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        terminal(active_terminal()).lock().scroll_up(lines);
+    });
+}
+
+/// Scrolls the view of the terminal shown down by `lines`.
+pub fn scroll_down(lines: usize) {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        terminal(active_terminal()).lock().scroll_down(lines);
+    });
+}
+
+/// Blanks the screen, the next prints start at its top left corner.
+pub fn clear_screen() {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        WRITER.lock().clear();
+    });
+}
+
+/// Prints with `color_code` until the returned guard is dropped.
+///
+/// The guards can be nested, also by interrupt handlers.
+pub fn set_color(color_code: ColorCode) -> ColorGuard {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        ColorGuard { id: WRITER.lock().push_color(color_code) }
+    })
+}
+
+impl Drop for ColorGuard {
+    fn drop(&mut self) {
+        use x86_64::instructions::interrupts;
+        if let Some(id) = self.id {
+            interrupts::without_interrupts(|| {
+                WRITER.lock().pop_color(id);
+            });
+        }
+    }
+}
+
+
+
+#[test_case]
+fn test_println_simple() {
+    println!("test_println_simple output");
+}
+
+
+#[test_case]
+fn test_println_many() {
+    for _ in 0..200 {
+        println!("test_println_many output");
+    }
+}
+
+#[test_case]
+fn test_println_output() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let s = "Some test string that fits on a single line";
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\n{}", s).expect("writeln failed");
+
+        let row = writer.row_position - 1;
+        for (i, c) in  s.chars().enumerate() {
+            let screen_char = writer.console.read(row, i);
+            assert_eq!(char::from(screen_char.ascii_character), c);
+        }
+    });
+}
+
+#[test_case]
+fn test_println_unsuported_chars() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let s = "®×€\u{1}";
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\n{}", s).expect("writeln failed");
+
+        // a single '■' per character
+        let row = writer.row_position - 1;
+        for i in 0..writer.width() {
+            let screen_char = writer.console.read(row, i);
+            let expected = if i < s.chars().count() { 0xfe } else { b' ' };
+            assert_eq!(screen_char.ascii_character, expected);
+        }
+    });
+}
+
+#[test_case]
+fn test_println_cp437_chars() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let s = "éèâàäçßñ╔═╗░▒▓█☺◙";
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\n{}", s).expect("writeln failed");
+
+        let row = writer.row_position - 1;
+        for (i, c) in s.chars().enumerate() {
+            let screen_char = writer.console.read(row, i);
+            assert_eq!(Some(screen_char.ascii_character), cp437::encode(c));
+        }
+    });
+}
+
+#[test_case]
+fn test_control_characters() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("\nabc\x08\x08d\te\rf");
+
+        let row = writer.row_position;
+        let line: [u8; 10] = core::array::from_fn(|i| {
+            writer.console.read(row, i).ascii_character
+        });
+        assert_eq!(&line, b"fd      e ");
+        assert_eq!(writer.column_position, 1);
+    });
+}
+
+#[test_case]
+fn test_clear_screen() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("some text");
+        writer.clear();
+
+        assert_eq!((writer.row_position, writer.column_position), (0, 0));
+        for row in 0..writer.height() {
+            for col in 0..writer.width() {
+                assert_eq!(writer.console.read(row, col).ascii_character, b' ');
+            }
+        }
+    });
+}
+
+#[test_case]
+fn test_escape_sequences() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("\x1b[2J\x1b[3;5Hab\x1b[1;31mc\x1b[0m\x1b[2Dd\x1b[Ae");
+
+        let char_at = |row: usize, col: usize| writer.console.read(row, col);
+        assert_eq!(char_at(2, 4).ascii_character, b'a');
+        assert_eq!(char_at(2, 5).ascii_character, b'd');
+        assert_eq!(char_at(2, 6).ascii_character, b'c');
+        assert_eq!(char_at(2, 6).color_code, ColorCode::new(Color::LightRed, Color::Black));
+        assert_eq!(char_at(2, 5).color_code, NORMAL_COLOR);
+        assert_eq!(char_at(1, 6).ascii_character, b'e');
+
+        writer.write_string("\x1b[1K");
+        assert_eq!(writer.console.read(1, 6).ascii_character, b' ');
+        writer.write_string("\n");
+    });
+}
+
+#[test_case]
+fn test_true_colors() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("\x1b[38;2;255;128;0;48;5;21;44m");
+        let orange = Rgb::new(255, 128, 0);
+        assert_eq!(writer.colors.text, Some(orange));
+        assert_eq!(writer.colors.background, None);
+        assert_eq!(writer.color(), ColorCode::new(Color::LightRed, Color::Blue));
+
+        let cell = writer.colors.cell(b'x');
+        assert_eq!((cell.text, cell.background), (orange, Color::Blue.into()));
+        writer.write_string("\x1b[39m");
+        assert_eq!(writer.colors.text, None);
+        writer.write_string("\x1b[0m");
+        assert_eq!(writer.colors, Colors::new(NORMAL_COLOR));
+    });
+}
+
+#[test_case]
+fn test_scrollback() {
+    use x86_64::instructions::interrupts;
+
+    init_scrollback();
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("\nscrolled off");
+        for _ in 0..writer.height() {
+            writer.write_string("\n");
+        }
+
+        let first_char = |writer: &Writer, row: usize| writer.console.read(row, 0).ascii_character;
+        writer.scroll_up(1);
+        assert_eq!(first_char(&writer, 0), b's');
+        writer.scroll_down(1);
+        assert_eq!(first_char(&writer, 0), b' ');
+
+        // new output snaps back to the bottom
+        writer.scroll_up(1);
+        writer.write_string("x");
+        assert_eq!(first_char(&writer, 0), b' ');
+        assert_eq!(first_char(&writer, writer.height() - 1), b'x');
+        writer.write_string("\n");
+    });
+}
+
+#[test_case]
+fn test_color_guards() {
+    let outer = ColorCode::new(Color::Yellow, Color::Blue);
+    let inner = ColorCode::new(Color::Green, Color::Black);
+    let color = || {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().color())
+    };
+
+    let previous = color();
+    let outer_guard = set_color(outer);
+    let inner_guard = set_color(inner);
+    assert_eq!(color(), inner);
+    cprint!(ERR_COLOR, "");
+    assert_eq!(color(), inner);
+
+    // dropped out of order, as by another thread
+    drop(outer_guard);
+    assert_eq!(color(), inner);
+    drop(inner_guard);
+    assert_eq!(color(), previous);
+}
+
+#[test_case]
+fn test_eprintln_keeps_escape_sequence_color() {
+    let color = || {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().color())
+    };
+
+    print!("\x1b[32m");
+    let green = color();
+    eprintln!("test_eprintln_keeps_escape_sequence_color output");
+    assert_eq!(color(), green);
+    print!("\x1b[0m");
+}
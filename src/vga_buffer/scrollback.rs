@@ -0,0 +1,105 @@
+//! Lines scrolled off the top of the screen, that can be viewed again.
+//!
+//! The memory is allocated once, by `init_scrollback` for the terminal
+//! shown and by `switch_terminal` for the others, so that printing never
+//! allocates, which could deadlock when printing a panic of the allocator.
+//! The lines a terminal prints before it is first shown are not kept.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::ScreenChar;
+use crate::console::Console;
+
+/// Number of lines kept once they scrolled off the screen
+pub(super) const SCROLLBACK_LINES: usize = 500;
+
+pub(super) struct Scrollback {
+    /// Columns of the console
+    width: usize,
+    /// Ring of `SCROLLBACK_LINES` lines of `width` characters
+    history: Vec<ScreenChar>,
+    /// Line of `history` of the oldest line
+    first: usize,
+    /// Number of lines in `history`
+    len: usize,
+    /// Content of the screen while the view is scrolled
+    screen: Vec<ScreenChar>,
+    /// Number of lines the view is scrolled up by
+    offset: usize,
+}
+
+impl Scrollback {
+    /// Allocates the history of `console`.
+    pub(super) fn new(console: &dyn Console, blank: ScreenChar) -> Self {
+        let width = console.width();
+        Self {
+            width,
+            history: vec![blank; SCROLLBACK_LINES * width],
+            first: 0,
+            len: 0,
+            screen: vec![blank; console.height() * width],
+            offset: 0,
+        }
+    }
+
+    /// Keeps the top row of `console` before it scrolls off the screen.
+    pub(super) fn push(&mut self, console: &dyn Console) {
+        let line = (self.first + self.len) % SCROLLBACK_LINES;
+        if self.len == SCROLLBACK_LINES {
+            self.first = (self.first + 1) % SCROLLBACK_LINES;
+        } else {
+            self.len += 1;
+        }
+        let start = line * self.width;
+        for (col, c) in self.history[start..start + self.width].iter_mut().enumerate() {
+            *c = console.read(0, col);
+        }
+    }
+
+    pub(super) fn is_scrolled(&self) -> bool {
+        self.offset != 0
+    }
+
+    /// Scrolls the view of `console` up by `lines`, as far as the oldest
+    /// line.
+    pub(super) fn scroll_up(&mut self, console: &mut dyn Console, lines: usize) {
+        if self.offset == 0 {
+            for (i, c) in self.screen.iter_mut().enumerate() {
+                *c = console.read(i / self.width, i % self.width);
+            }
+        }
+        self.offset = (self.offset + lines).min(self.len);
+        self.render(console);
+    }
+
+    /// Scrolls the view of `console` down by `lines`, as far as the screen.
+    pub(super) fn scroll_down(&mut self, console: &mut dyn Console, lines: usize) {
+        if self.offset != 0 {
+            self.offset = self.offset.saturating_sub(lines);
+            self.render(console);
+        }
+    }
+
+    /// Scrolls the view back to the screen.
+    pub(super) fn snap_back(&mut self, console: &mut dyn Console) {
+        self.scroll_down(console, self.offset);
+    }
+
+    fn render(&self, console: &mut dyn Console) {
+        let top = self.len - self.offset;
+        for row in 0..console.height() {
+            let line = top + row;
+            let line = if line < self.len {
+                let start = (self.first + line) % SCROLLBACK_LINES * self.width;
+                &self.history[start..start + self.width]
+            } else {
+                let start = (line - self.len) * self.width;
+                &self.screen[start..start + self.width]
+            };
+            for (col, &c) in line.iter().enumerate() {
+                console.write(row, col, c);
+            }
+        }
+    }
+}
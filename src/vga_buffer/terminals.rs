@@ -0,0 +1,202 @@
+//! Virtual terminals: writers with a screen of their own, one of them shown
+//! on the console at a time.
+//!
+//! Each terminal writes on a `Screen`, that keeps a copy of its cells once
+//! `init_terminals` is called, and also draws them on the console while the
+//! terminal is the one shown. `switch_terminal` draws the copy of another
+//! terminal on the console and shows it from then on.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use super::{show_cursor, Buffer, ScreenChar, Writer, NORMAL_COLOR, VGA_ADDRESS};
+use crate::console::Console;
+
+/// Number of virtual terminals, switched to with Alt+F1 to Alt+F6
+pub const TERMINAL_COUNT: usize = 6;
+
+/// Index of the terminal shown on the console, only changed with `DISPLAY`
+/// locked
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// The console the shown terminal draws on, the VGA text buffer until
+    /// `set_console` is called
+    ///
+    /// Only locked with interrupts disabled, and after the `Writer` of a
+    /// terminal if both are.
+    pub(super) static ref DISPLAY: Mutex<&'static mut dyn Console> = {
+        show_cursor();
+        Mutex::new(unsafe { &mut *(VGA_ADDRESS as *mut Buffer) })
+    };
+
+    /// Writers of the terminals, locked in order when several are
+    pub(super) static ref TERMINALS: [Mutex<Writer>; TERMINAL_COUNT] =
+        core::array::from_fn(|index| Mutex::new(Writer::new(Screen::new(index))));
+}
+
+/// Cells of a virtual terminal, drawn on the console while it is shown
+pub(super) struct Screen {
+    index: usize,
+    width: usize,
+    height: usize,
+    /// Copy of the cells, empty until `init_terminals` is called
+    cells: Vec<ScreenChar>,
+    cursor: Option<(usize, usize)>,
+}
+
+impl Screen {
+    /// The screen of terminal `index`, as large as the console
+    fn new(index: usize) -> Self {
+        let display = DISPLAY.lock();
+        Self {
+            index,
+            width: display.width(),
+            height: display.height(),
+            cells: Vec::new(),
+            cursor: None,
+        }
+    }
+
+    /// Keeps a copy of the cells from now on, the ones on the console if
+    /// the terminal is shown, blank ones otherwise.
+    pub(super) fn keep_cells(&mut self) {
+        if !self.cells.is_empty() {
+            return;
+        }
+        self.cells = vec![ScreenChar::new(b' ', NORMAL_COLOR); self.width * self.height];
+        self.with_display(|display, cells| {
+            for (index, cell) in cells.iter_mut().enumerate() {
+                *cell = display.read(index / display.width(), index % display.width());
+            }
+        });
+    }
+
+    /// Takes the size of the new console, the cells being blanked.
+    pub(super) fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.cursor = None;
+        if !self.cells.is_empty() {
+            self.cells.clear();
+            self.keep_cells();
+        }
+    }
+
+    /// Calls `f` with the console and the copy of the cells, if the
+    /// terminal is shown.
+    fn with_display(&mut self, f: impl FnOnce(&mut dyn Console, &mut [ScreenChar])) {
+        let mut display = DISPLAY.lock();
+        if ACTIVE.load(Ordering::Acquire) == self.index {
+            f(&mut **display, &mut self.cells);
+        }
+    }
+}
+
+impl Console for Screen {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read(&self, row: usize, column: usize) -> ScreenChar {
+        match self.cells.is_empty() {
+            false => self.cells[row * self.width + column],
+            // only the shown terminal writes before `init_terminals`
+            true => DISPLAY.lock().read(row, column),
+        }
+    }
+
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar) {
+        if !self.cells.is_empty() {
+            self.cells[row * self.width + column] = c;
+        }
+        self.with_display(|display, _| display.write(row, column, c));
+    }
+
+    fn set_cursor(&mut self, position: Option<(usize, usize)>) {
+        self.cursor = position;
+        self.with_display(|display, _| display.set_cursor(position));
+    }
+
+    fn scroll(&mut self) {
+        if !self.cells.is_empty() {
+            self.cells.copy_within(self.width.., 0);
+        }
+        self.with_display(|display, _| display.scroll());
+    }
+}
+
+/// Keeps a copy of the screen of each terminal, so that they can be
+/// switched to. Needs the heap.
+pub fn init_terminals() {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        for terminal in TERMINALS.iter() {
+            terminal.lock().console.keep_cells();
+        }
+    });
+}
+
+/// The writer of terminal `index`, see the `tprint!` macro
+///
+/// Panics if there is no such terminal.
+pub fn terminal(index: usize) -> &'static Mutex<Writer> {
+    &TERMINALS[index]
+}
+
+/// Index of the terminal shown
+pub fn active_terminal() -> usize {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// Shows terminal `index` on the console, if `init_terminals` was called.
+/// The first time, also starts its scrollback history.
+///
+/// Panics if there is no such terminal.
+pub fn switch_terminal(index: usize) {
+    use x86_64::instructions::interrupts;
+    interrupts::without_interrupts(|| {
+        let mut writer = TERMINALS[index].lock();
+        if writer.console.cells.is_empty() {
+            return;
+        }
+        writer.keep_scrollback();
+        let screen = &writer.console;
+        let mut display = DISPLAY.lock();
+        ACTIVE.store(index, Ordering::Release);
+        for (i, &c) in screen.cells.iter().enumerate() {
+            display.write(i / screen.width, i % screen.width, c);
+        }
+        display.set_cursor(screen.cursor);
+    });
+}
+
+#[test_case]
+fn test_switch_terminals() {
+    use x86_64::instructions::interrupts;
+
+    init_terminals();
+    let shown = active_terminal();
+    let other = (shown + 1) % TERMINAL_COUNT;
+    let displayed = || interrupts::without_interrupts(|| DISPLAY.lock().read(0, 0));
+    let before = displayed();
+
+    crate::tprint!(other, "\x1b[2J\x1b[1;1Hhidden");
+    assert_eq!(displayed(), before);
+    switch_terminal(other);
+    assert_eq!(active_terminal(), other);
+    assert_eq!(displayed().ascii_character, b'h');
+    assert!(interrupts::without_interrupts(|| TERMINALS[other].lock().scrollback.is_some()));
+
+    switch_terminal(shown);
+    crate::tprint!(other, "\x1b[1;1Hx");
+    assert_eq!(displayed(), before);
+}
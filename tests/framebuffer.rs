@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(swag_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use swag_kernel::hlt_loop;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use swag_kernel::allocator;
+    use swag_kernel::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    swag_kernel::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    swag_kernel::test_panic_handler(info)
+}
+
+
+use swag_kernel::console::Console;
+use swag_kernel::framebuffer::{self, Error, Font, FramebufferConsole, Rgb};
+use swag_kernel::vga_buffer::{Color, ColorCode, ScreenChar};
+
+#[test_case]
+fn console_draws_glyphs() {
+    let framebuffer = framebuffer::init(640, 480).expect("no framebuffer");
+    assert_eq!(framebuffer::init(640, 480).err(), Some(Error::AlreadyInitialized));
+
+    let font = Font::default();
+    let mut console = FramebufferConsole::new(framebuffer, font);
+    assert_eq!((console.width(), console.height()), (80, 30));
+
+    let block = ScreenChar::new(0xdb, ColorCode::new(Color::Yellow, Color::Blue));
+    console.write(1, 2, block);
+    let framebuffer = console.framebuffer();
+    assert_eq!(framebuffer.pixel(2 * 8, 16), Rgb::from(Color::Yellow));
+    assert_eq!(framebuffer.pixel(3 * 8 - 1, 2 * 16 - 1), Rgb::from(Color::Yellow));
+    assert_eq!(framebuffer.pixel(3 * 8, 16), Rgb::from(Color::Black));
+
+    let space = ScreenChar { ascii_character: b' ', ..block };
+    console.write(1, 2, space);
+    assert_eq!(console.framebuffer().pixel(2 * 8, 16), Rgb::from(Color::Blue));
+
+    // true colors are drawn instead of the ones of the color code
+    let orange = ScreenChar { text: Rgb::new(255, 128, 0), ..block };
+    console.write(1, 2, orange);
+    assert_eq!(console.framebuffer().pixel(2 * 8, 16), Rgb::new(255, 128, 0));
+}
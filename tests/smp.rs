@@ -0,0 +1,157 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(swag_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use conquer_once::spin::OnceCell;
+use swag_kernel::task::executor::{Executor, Spawner};
+use swag_kernel::task::Task;
+use swag_kernel::{hlt_loop, memory, smp, thread};
+use x86_64::structures::paging::{Page, PhysFrame};
+use x86_64::PhysAddr;
+
+/// CPUs given to QEMU in the test arguments
+const CPUS: usize = 4;
+
+/// Bit `i` is set by the AP of index `i` when it runs `ap_main`
+static STARTED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Incremented by the threads spawned on the APs
+static AP_THREADS_FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Spawner of an executor that only runs on the APs
+static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use swag_kernel::allocator;
+    use swag_kernel::memory::BootInfoFrameAllocator;
+    use x86_64::VirtAddr;
+
+    swag_kernel::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    thread::init();
+
+    // never run on the BSP, its tasks have to be stolen by the APs
+    let executor = alloc::boxed::Box::leak(alloc::boxed::Box::new(Executor::new()));
+    SPAWNER.init_once(|| executor.spawner());
+    smp::start_aps(ap_main);
+
+    test_main();
+    hlt_loop();
+}
+
+fn ap_main() -> ! {
+    let cpu = smp::this_cpu().expect("per-CPU data not set");
+    STARTED_CPUS.fetch_or(1 << cpu.index, Ordering::AcqRel);
+
+    // runs on this AP's scheduler, next to the boot thread
+    let handle = thread::spawn(thread::yield_now);
+    handle.join();
+    AP_THREADS_FINISHED.fetch_add(1, Ordering::AcqRel);
+
+    let spawner = SPAWNER.get().expect("executor not created");
+    Executor::join(spawner).run();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    swag_kernel::test_panic_handler(info)
+}
+
+
+#[test_case]
+fn all_cpus_are_started() {
+    assert_eq!(smp::cpu_count(), CPUS);
+}
+
+#[test_case]
+fn bootstrap_processor_has_index_0() {
+    assert_eq!(smp::cpu_index(), 0);
+}
+
+#[test_case]
+fn each_ap_runs_main() {
+    let all_aps = ((1 << CPUS) - 1) & !1;
+    while STARTED_CPUS.load(Ordering::Acquire) != all_aps {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn threads_run_on_the_aps() {
+    while AP_THREADS_FINISHED.load(Ordering::Acquire) != CPUS - 1 {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn call_on_runs_on_the_target_cpu() {
+    static RAN_ON: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    for cpu in 0..CPUS {
+        smp::call_on(cpu, || RAN_ON.store(smp::cpu_index(), Ordering::Release));
+        assert_eq!(RAN_ON.load(Ordering::Acquire), cpu);
+    }
+}
+
+#[test_case]
+fn call_on_others_waits_for_every_cpu() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    smp::call_on_others(|| {
+        CALLS.fetch_add(1, Ordering::AcqRel);
+    });
+    assert_eq!(CALLS.load(Ordering::Acquire), CPUS - 1);
+}
+
+#[test_case]
+fn unmap_returns_the_mapped_frame() {
+    let vga_buffer = PhysAddr::new(0xb8000);
+    let addr = memory::map_mmio(vga_buffer, 4096);
+    let page = Page::containing_address(addr);
+
+    let frame = memory::unmap(page).expect("page not mapped");
+    assert_eq!(frame, PhysFrame::containing_address(vga_buffer));
+    assert!(memory::unmap(page).is_err());
+}
+
+#[test_case]
+fn empty_mmio_ranges_map_nothing() {
+    let addr = memory::map_mmio(PhysAddr::new(0xb8000), 0);
+    assert!(memory::unmap(Page::containing_address(addr)).is_err());
+    memory::unmap_mmio(addr, 0);
+}
+
+#[test_case]
+fn tasks_spawned_on_the_bsp_run_on_the_aps() {
+    static RAN_ON: AtomicUsize = AtomicUsize::new(0);
+    const TASKS: usize = 8;
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+    let spawner = SPAWNER.get().expect("executor not created");
+    for _ in 0..TASKS {
+        spawner.spawn(Task::new(async {
+            RAN_ON.fetch_or(1 << smp::cpu_index(), Ordering::AcqRel);
+            FINISHED.fetch_add(1, Ordering::AcqRel);
+        }));
+    }
+    while FINISHED.load(Ordering::Acquire) != TASKS {
+        core::hint::spin_loop();
+    }
+    assert_eq!(RAN_ON.load(Ordering::Acquire) & 1, 0);
+}
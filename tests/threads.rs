@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(swag_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use swag_kernel::{hlt_loop, thread};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use swag_kernel::allocator;
+    use swag_kernel::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    swag_kernel::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    thread::init();
+
+    test_main();
+    hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    swag_kernel::test_panic_handler(info)
+}
+
+
+#[test_case]
+fn busy_thread_is_preempted() {
+    static RAN: AtomicBool = AtomicBool::new(false);
+
+    thread::spawn(|| RAN.store(true, Ordering::Relaxed));
+    // never yields, so only the timer interrupt can let the thread run
+    while !RAN.load(Ordering::Relaxed) {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn many_threads_run_to_completion() {
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    const THREADS: usize = 10;
+
+    for _ in 0..THREADS {
+        thread::spawn(|| {
+            thread::yield_now();
+            FINISHED.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    while FINISHED.load(Ordering::Relaxed) < THREADS {
+        thread::yield_now();
+    }
+}
+
+#[test_case]
+fn sleep_waits_for_the_duration() {
+    use core::time::Duration;
+    use swag_kernel::interrupts::{ticks, TIMER_FREQUENCY};
+
+    let start = ticks();
+    thread::sleep(Duration::from_millis(100));
+    assert!(ticks() - start >= TIMER_FREQUENCY as u64 / 10);
+}
+
+#[test_case]
+fn join_returns_the_result() {
+    let handle = thread::spawn(|| 6 * 7);
+    assert_eq!(handle.join(), 42);
+}
+
+#[test_case]
+fn mutex_is_exclusive() {
+    use alloc::vec::Vec;
+    static COUNTER: thread::Mutex<usize> = thread::Mutex::new(0);
+    const THREADS: usize = 4;
+    const INCREMENTS: usize = 1000;
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| thread::spawn(|| {
+            for _ in 0..INCREMENTS {
+                let mut counter = COUNTER.lock();
+                let value = *counter;
+                // give the others a chance to race us
+                thread::yield_now();
+                *counter = value + 1;
+            }
+        }))
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(*COUNTER.lock(), THREADS * INCREMENTS);
+}
+
+#[test_case]
+fn block_on_waits_for_a_future() {
+    use swag_kernel::task::sync::oneshot;
+
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        thread::yield_now();
+        sender.send(42).unwrap();
+    });
+    assert_eq!(thread::block_on(receiver), Ok(42));
+}